@@ -0,0 +1,171 @@
+//! Every message the contract panics or logs an error with. Clients can match on these strings:
+//! they only change together with the contract version. `{}` marks a value filled in with `fill`.
+
+use std::fmt::Display;
+
+// Initialization.
+pub const ERR_ALREADY_INITIALIZED: &str = "Already initialized";
+pub const ERR_ZERO_COPIES: &str = "copies must be a positive number";
+pub const ERR_NO_STATE_TO_MIGRATE: &str = "Error: No contract state to migrate";
+
+// Access control.
+pub const ERR_NOT_OWNER: &str = "Owner only";
+pub const ERR_NOT_ADMIN_OR_OWNER: &str = "Owner or admin only";
+pub const ERR_NOT_ADMIN: &str = "Error: Not an admin";
+pub const ERR_ACCOUNT_BLOCKED: &str = "Error: Account {} is blocked";
+pub const ERR_NOT_BLOCKED: &str = "Error: Account is not blocked";
+pub const ERR_NOT_TOKEN_OWNER: &str = "Error: Token not owned by the caller";
+pub const ERR_PREDECESSOR_NOT_OWNER: &str = "Predecessor must be token owner.";
+
+// Tokens.
+pub const ERR_TOKEN_NOT_FOUND: &str = "Error: No token_id found";
+pub const ERR_UNKNOWN_TOKEN: &str = "Token not found";
+pub const ERR_INVALID_TIMESTAMP: &str = "Error: Invalid timestamp";
+pub const ERR_INVALID_TOKEN_RANGE: &str = "Error: Invalid token id range";
+pub const ERR_TOKEN_RANGE_TOO_WIDE: &str = "Error: Token id range can span at most {} ids";
+pub const ERR_TOO_MANY_TOKEN_IDS: &str = "Error: Cannot look up more than {} tokens at once";
+pub const ERR_SEQUENTIAL_TOKEN_ID: &str = "Error: Numeric token ids are reserved for sequential tickets";
+pub const ERR_TOKEN_ID_EXISTS: &str = "Token id already exists";
+pub const ERR_TOKEN_ID_NOT_FREED: &str = "Error: Only ids freed by a burn can be reissued";
+pub const ERR_BURNED_COUNT_OUT_OF_SYNC: &str = "Error: The burned token count is out of sync, run reconcile_minted";
+
+// Supply.
+pub const ERR_SOLD_OUT: &str = "Error: Sold out";
+pub const ERR_MINTING_CLOSED: &str = "Minting closed";
+pub const ERR_NOT_ENOUGH_TICKETS: &str = "Error: Not enough tickets left";
+pub const ERR_EXCEEDS_HARD_SUPPLY: &str = "Exceeds hard supply ceiling";
+pub const ERR_SUPPLY_INVARIANT: &str = "Error: Supply invariant violated";
+pub const ERR_SUPPLY_NOT_INCREASED: &str = "Error: Supply can only be increased";
+pub const ERR_SUPPLY_OVERFLOW: &str = "Error: Supply overflow";
+pub const ERR_SUPPLY_BELOW_MINTED: &str = "Error: Supply can't go below the minted tickets";
+pub const ERR_ALREADY_MINTED: &str = "Error: Tickets have already been minted";
+pub const ERR_METADATA_FROZEN: &str = "Error: Metadata is frozen";
+pub const ERR_INVALID_BASE_URI: &str = "Error: Base URI must start with http://, https:// or ipfs://";
+
+// Buying and minting.
+pub const ERR_INSUFFICIENT_DEPOSIT: &str = "Error: Must attach at least the {} yoctoNEAR price";
+pub const ERR_BUY_DEPOSIT: &str = "Error: Must attach {} yoctoNEAR to cover the minting price and storage";
+pub const ERR_BUY_GAS: &str = "Attach at least {} gas";
+pub const ERR_REQUEST_ID_TOO_LONG: &str = "Error: Request ids can be at most {} characters";
+pub const ERR_BUY_REQUEST_TOKEN_GONE: &str = "Error: Ticket from this request no longer exists";
+pub const ERR_SELF_REFERRAL: &str = "Error: Self-referrals are not allowed";
+pub const ERR_ATTRIBUTE_RESERVED: &str = "Error: The {} attribute is reserved";
+pub const ERR_GIFT_MESSAGE_TOO_LONG: &str = "Error: Gift messages can be at most {} characters";
+pub const ERR_TOO_MANY_EXTRA_ATTRIBUTES: &str = "Error: At most {} extra attributes are allowed";
+pub const ERR_EXTRA_ATTRIBUTE_TOO_LONG: &str = "Error: Attribute names and values can be at most {} characters";
+pub const ERR_INVALID_VALIDITY_WINDOW: &str = "Error: valid_from_ns must be before valid_until_ns";
+pub const ERR_TOO_MANY_BUYS_IN_BLOCK: &str = "Error: Too many buys from this account in one block";
+pub const ERR_ZERO_BUYS_PER_BLOCK: &str = "Error: Must allow at least one buy per block";
+pub const ERR_MINT_TO_CONTRACT: &str = "Cannot mint to contract account";
+pub const ERR_EMPTY_RECEIVER: &str = "Error: Receiver account id is empty";
+pub const ERR_INVALID_IMPLICIT_RECEIVER: &str = "Error: Invalid receiver {}: implicit account ids are 64 hex characters";
+pub const ERR_TOO_MANY_AIRDROP_RECIPIENTS: &str = "Error: Can airdrop to at most {} recipients at once";
+pub const ERR_ZERO_TRAIT_WEIGHTS: &str = "Error: At least one random trait needs a positive weight";
+pub const ERR_TIER_NOT_FOUND: &str = "Error: Tier not found";
+pub const ERR_TIER_EXISTS: &str = "Error: Tier already exists";
+pub const ERR_TIER_ALREADY_MINTED: &str = "Error: The tier already has sales";
+pub const ERR_TIER_SOLD_OUT: &str = "Error: Tier sold out";
+pub const ERR_UNKNOWN_PROMO_CODE: &str = "Error: Unknown promo code";
+pub const ERR_PROMO_CODE_EXHAUSTED: &str = "Error: Promo code exhausted";
+pub const ERR_DISCOUNT_TOO_HIGH: &str = "Error: Discount can't exceed 10000 basis points";
+pub const ERR_UNKNOWN_CLAIM_CODE: &str = "Error: Unknown claim code";
+pub const ERR_CODE_ALREADY_CLAIMED: &str = "Code already claimed";
+pub const ERR_CLAIM_CODE_NOT_HASHED: &str = "Error: Claim codes must be lowercase hex sha256 hashes";
+pub const ERR_TOO_MANY_CLAIM_CODES: &str = "Error: Can add at most {} claim codes at once";
+
+// Allowlist sales and transfer permits.
+pub const ERR_ALLOWLIST_SIGNER_NOT_SET: &str = "Error: Allowlist signer not set";
+pub const ERR_ALLOWLIST_SIGNER_LENGTH: &str = "Error: The allowlist signer must be a 32-byte ed25519 public key";
+pub const ERR_INVALID_ALLOWLIST_SIGNER: &str = "Error: Invalid allowlist signer";
+pub const ERR_SIGNATURE_EXPIRED: &str = "Error: Signature expired";
+pub const ERR_INVALID_SIGNATURE: &str = "Error: Invalid signature";
+pub const ERR_ALLOWLIST_NONCE_USED: &str = "Error: Allowlist nonce already used";
+pub const ERR_PERMIT_KEY_LENGTH: &str = "Error: Permit keys must be 32-byte ed25519 public keys";
+pub const ERR_PERMIT_KEY_NOT_SET: &str = "Error: Token owner has no permit key";
+pub const ERR_PERMIT_EXPIRED: &str = "Error: Permit expired";
+pub const ERR_PERMIT_NONCE_USED: &str = "Error: Permit nonce already used";
+
+// Fungible token payments and storage.
+pub const ERR_PAYMENT_TOKEN_NOT_SET: &str = "Error: Payment token not set";
+pub const ERR_PAYMENT_TOKEN_MISMATCH: &str = "Error: Token not accepted as payment";
+pub const ERR_FT_UNDERPAID: &str = "Error: Must transfer {} to buy a ticket, refunding {}";
+pub const ERR_INVALID_FT_MSG: &str = "Error: msg must be the receiver account id";
+pub const ERR_STORAGE_BALANCE_TOO_LOW: &str = "Error: Storage balance too low, top it up with storage_deposit";
+pub const ERR_REGISTRATION_DEPOSIT: &str = "Error: Must attach at least {} yoctoNEAR to register";
+pub const ERR_NOT_REGISTERED: &str = "Error: Account not registered";
+pub const ERR_STORAGE_WITHDRAW_TOO_HIGH: &str = "Error: Can't withdraw more than the available {} yoctoNEAR";
+
+// Redemption.
+pub const ERR_REDEMPTIONS_LOCKED: &str = "Error: Redemptions are locked";
+pub const ERR_ALREADY_REDEEMED: &str = "Error: Token already redeemed";
+pub const ERR_NOT_REDEEMED: &str = "Error: Token not redeemed";
+pub const ERR_NOT_YET_VALID: &str = "Ticket not yet valid";
+pub const ERR_EXPIRED: &str = "Ticket expired";
+pub const ERR_ZERO_MAX_REDEMPTIONS: &str = "Error: Tickets must allow at least one redemption";
+pub const ERR_REDEEM_BATCH_TOO_LARGE: &str = "Error: Can redeem at most {} tokens at once";
+
+// Transfers and approvals.
+pub const ERR_ONE_YOCTO_REQUIRED: &str = "Requires attached deposit of at least 1 yoctoNEAR";
+pub const ERR_TRANSFER_FEE_DEPOSIT: &str = "Error: Must attach the {} yoctoNEAR transfer fee plus 1 yoctoNEAR";
+pub const ERR_TRANSFER_HISTORY_DEPOSIT: &str =
+    "Error: Must attach {} yoctoNEAR more to cover the transfer history storage";
+pub const ERR_ROYALTY_DEPOSIT: &str = "Error: Must attach {} yoctoNEAR of royalties on top of the transfer deposit";
+pub const ERR_TRANSFER_COOLDOWN: &str = "Token in transfer cooldown";
+pub const ERR_RECIPIENT_HOLDS_TOO_MANY: &str = "Recipient holds too many tickets";
+pub const ERR_ATTENDANCE_TOKEN_TRANSFER: &str = "Error: Attendance tokens can't be transferred";
+pub const ERR_REDEEMED_TRANSFER_LOCKED: &str = "Redeemed tickets are non-transferable";
+pub const ERR_CALL_GAS_TOO_HIGH: &str =
+    "Error: Not enough gas left for the receiver call and resolving the transfer";
+pub const ERR_MARKETPLACE_NOT_ALLOWLISTED: &str = "Marketplace not allowlisted";
+pub const ERR_MARKETPLACE_NOT_LISTED: &str = "Error: Marketplace not on the list";
+pub const ERR_TOO_MANY_APPROVALS: &str = "Too many approvals";
+pub const ERR_TOO_MANY_APPROVE_MANY: &str = "Error: Can approve at most {} tokens at once";
+pub const ERR_ZERO_APPROVALS: &str = "Error: Tokens must allow at least one approval";
+
+// Payouts, royalties and proceeds.
+pub const ERR_PAYOUT_MAX_LEN: &str = "Market cannot payout to that many receivers";
+pub const ERR_PAYOUT_RECORD_DEPOSIT: &str = "Error: Must attach {} yoctoNEAR more to record the payout for confirmation";
+pub const ERR_NO_PENDING_PAYOUT: &str = "Error: No payout awaiting confirmation for this token";
+pub const ERR_NOT_PAYOUT_MARKETPLACE: &str = "Error: Only the marketplace that settled the sale can confirm its payout";
+pub const ERR_ZERO_SALE_PRICE: &str = "Sale price must be positive";
+pub const ERR_ROYALTIES_ABOVE_CAP: &str = "Error: Royalties add up to {} basis points, above the {} cap";
+pub const ERR_ROYALTY_CAP_TOO_HIGH: &str = "Error: Royalty cap can't exceed 10000 basis points";
+pub const ERR_REFERRAL_BPS_TOO_HIGH: &str = "Error: Referral share can't exceed 10000 basis points";
+pub const ERR_PRIMARY_SHARES_TOO_HIGH: &str =
+    "Error: Primary split and referral share can't exceed 10000 basis points";
+pub const ERR_TREASURY_SPLIT_TOTAL: &str = "Error: Treasury split must add up to 10000 basis points";
+pub const ERR_WITHDRAW_ABOVE_PROCEEDS: &str = "Error: Can't withdraw more than the {} yoctoNEAR of proceeds";
+
+// Refunds.
+pub const ERR_NOT_REFUNDABLE: &str = "Error: Ticket is not refundable";
+pub const ERR_REFUND_ABOVE_PROCEEDS: &str = "Error: Not enough proceeds left to refund";
+pub const ERR_REFUND_IN_PROGRESS: &str = "Error: A refund of this ticket is in progress";
+pub const ERR_PARTIAL_REFUND_TOO_HIGH: &str = "Error: Partial refund can't exceed 10000 basis points";
+
+// Consignments.
+pub const ERR_OWNER_CANNOT_CONSIGN: &str = "Error: The owner can't consign tickets";
+pub const ERR_ZERO_ASKING_PRICE: &str = "Error: Asking price must be positive";
+pub const ERR_CONSIGNMENT_DEPOSIT: &str = "Error: Must attach {} yoctoNEAR more to cover the consignment storage";
+pub const ERR_NOT_CONSIGNED: &str = "Error: Ticket is not consigned";
+pub const ERR_CONSIGNED: &str = "Error: Ticket is consigned for resale";
+pub const ERR_NOT_CONSIGNOR: &str = "Error: Only the consignor or the owner can cancel a consignment";
+pub const ERR_RESALE_DEPOSIT: &str = "Error: Must attach the {} yoctoNEAR asking price plus 1 yoctoNEAR";
+pub const ERR_COMMISSION_TOO_HIGH: &str = "Error: Commission can't exceed 10000 basis points";
+
+// Waitlist.
+pub const ERR_STILL_ON_SALE: &str = "Error: Tickets are still on sale";
+pub const ERR_ALREADY_ON_WAITLIST: &str = "Error: Already on the waitlist";
+pub const ERR_WAITLIST_FULL: &str = "Error: Waitlist is full";
+pub const ERR_WAITLIST_DEPOSIT: &str = "Error: Must attach {} yoctoNEAR to join the waitlist";
+pub const ERR_NOT_ON_WAITLIST: &str = "Error: Not on the waitlist";
+
+/// Fills the `{}` placeholders of `template` with `args`, in order.
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut message = parts.next().unwrap_or_default().to_string();
+    for (part, arg) in parts.zip(args) {
+        message.push_str(&arg.to_string());
+        message.push_str(part);
+    }
+    message
+}
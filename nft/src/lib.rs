@@ -15,42 +15,443 @@ NOTES:
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
 */
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
 use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
-use near_contract_standards::non_fungible_token::{Token, TokenId, bytes_for_approved_account_id};
+use near_contract_standards::non_fungible_token::approval::{ext_nft_approval_receiver, NonFungibleTokenApproval};
+use near_contract_standards::non_fungible_token::{Token, TokenId, bytes_for_approved_account_id, refund_deposit};
+use near_contract_standards::non_fungible_token::core::{NonFungibleTokenCore, NonFungibleTokenResolver};
+use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+use near_contract_standards::non_fungible_token::events::{NftBurn, NftMint};
 use near_contract_standards::non_fungible_token::NonFungibleToken;
-use near_sdk::{assert_one_yocto, Balance};
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds, StorageManagement};
+use near_sdk::{assert_one_yocto, Balance, BlockHeight};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U128};
 use near_sdk::serde::{Serialize, Deserialize};
 use near_sdk::{
-    env, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault, Promise, PromiseOrValue,
-    serde_json::json
+    env, ext_contract, near_bindgen, AccountId, BorshStorageKey, Gas, PanicOnDefault, Promise,
+    PromiseOrValue, PromiseResult, serde_json::{self, json}
 };
 
+mod errors;
+use errors::*;
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Payout {
     pub payout: HashMap<AccountId, U128>,
 } 
 
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Tier {
+    price: u128,
+    max_supply: u64,
+    minted: u64,
+    redeemed: u64,
+    /// Replaces the contract's `token_metadata` template for this tier's tickets, if set.
+    metadata: Option<TokenMetadata>,
+}
+
+impl Tier {
+    fn view(&self, name: String) -> TierView {
+        TierView {
+            name,
+            price: U128(self.price),
+            max_supply: self.max_supply,
+            minted: self.minted,
+            remaining: self.max_supply.saturating_sub(self.minted),
+            sold_out: self.minted >= self.max_supply,
+            redeemed: self.redeemed,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TierView {
+    pub name: String,
+    pub price: U128,
+    pub max_supply: u64,
+    pub minted: u64,
+    pub remaining: u64,
+    pub sold_out: bool,
+    pub redeemed: u64,
+}
+
+/// A discount on the minting price, redeemable at most `max_uses` times if set.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PromoCode {
+    pub discount_bps: u32,
+    pub max_uses: Option<u64>,
+    pub uses: u64,
+}
+
+/// How much of the price paid `refund_ticket` returns: all of it until `full_refund_window_ns`
+/// before the event, `partial_refund_bps` of it from then until the event, and nothing after.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundPolicy {
+    pub full_refund_window_ns: u64,
+    pub partial_refund_bps: u32,
+}
+
+/// One hop in a ticket's ownership chain, kept by `transfer_history`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferRecord {
+    pub from: AccountId,
+    pub to: AccountId,
+    pub timestamp: u64,
+    pub memo: Option<String>,
+}
+
+/// A marketplace sale whose `confirm_payout` didn't match what `nft_transfer_payout` returned.
+/// The `nft_payout_mismatch` event logged with it has the expected and reported amounts.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FlaggedPayout {
+    pub token_id: TokenId,
+    pub marketplace_id: AccountId,
+    pub flagged_at: u64,
+}
+
+/// The sale's headline numbers for storefronts, derived from the same state as the individual views.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MintingProgress {
+    pub minted: u64,
+    pub max_supply: u64,
+    pub remaining: u64,
+    pub price: U128,
+    pub paused: bool,
+    pub redemptions_locked: bool,
+    pub sale_status: String,
+}
+
+/// The numbers for an organizer's post-event report, read from the cached counters.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SalesSummary {
+    pub total_minted: u64,
+    pub total_redeemed: u64,
+    pub total_burned: u64,
+    pub gross_revenue: U128,
+    pub refunds_issued: U128,
+    pub current_balance: U128,
+}
+
+/// The contract metadata plus the event and sale details ticketing-aware wallets render.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TicketContractMetadata {
+    #[serde(flatten)]
+    pub metadata: NFTContractMetadata,
+    pub event_info: EventInfo,
+    pub price: U128,
+    pub total_supply: u64,
+    pub redeemed_count: u64,
+    pub soulbound: bool,
+    pub transfers_locked_until: Option<u64>,
+}
+
+/// A record of one redemption for access-control systems to sign or verify. It names the
+/// contract, so a record from one event's contract can't be replayed against another.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RedemptionProof {
+    pub contract_id: AccountId,
+    pub token_id: TokenId,
+    pub redeemer_id: AccountId,
+    pub redeemed_at_ns: u64,
+}
+
+/// A token with the ticket attributes dashboards need parsed from its `extra` JSON.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenDetailed {
+    #[serde(flatten)]
+    pub token: Token,
+    pub redeemed: bool,
+    pub seat: Option<String>,
+    pub tier: Option<String>,
+}
+
+/// Structured details of the event the tickets are for, so aggregators can list it without
+/// parsing the free-form `reference`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventInfo {
+    pub venue: String,
+    pub event_date_ns: u64,
+    pub doors_open_ns: Option<u64>,
+    pub city: Option<String>,
+}
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+    token_metadata: TokenMetadata,
+    minted_tokens: u64,
+    max_supply: u64,
+    minting_price: u128,
+    perpetual_royalties: Option<HashMap<AccountId, u32>>,
+    allowlist_signer: Option<Vec<u8>>,
+    total_revenue: u128,
+    redeemed_count: u64,
+    max_held_per_account: Option<u64>,
+    referral_bps: Option<u32>,
+    referral_earnings: LookupMap<AccountId, u128>,
+    tiers: UnorderedMap<String, Tier>,
+    transfer_fee: Option<u128>,
+    transfer_fee_on_plain_transfers: bool,
+    max_total_royalty_bps: u32,
+    event_info: EventInfo,
+    storage_balances: LookupMap<AccountId, Balance>,
+    redemption_grace_ns: u64,
+    transfer_cooldown_ns: Option<u64>,
+    minted_at: LookupMap<TokenId, u64>,
+    buy_requests: LookupMap<AccountId, Vec<(String, TokenId)>>,
+    metadata_frozen: bool,
+    payment_token: Option<(AccountId, u128)>,
+    hard_max_supply: u64,
+    promo_codes: LookupMap<String, PromoCode>,
+    transfer_memos: LookupMap<TokenId, String>,
+    transfer_history: LookupMap<TokenId, Vector<TransferRecord>>,
+    waitlist: Vector<(AccountId, Balance)>,
+    admins: UnorderedSet<AccountId>,
+    proceeds: Balance,
+    treasury_split: Option<HashMap<AccountId, u32>>,
+    poap_metadata: Option<TokenMetadata>,
+    poaps_minted: u64,
+    default_call_gas: u64,
+    redemptions_locked: bool,
+    refund_policy: Option<RefundPolicy>,
+    paid_prices: LookupMap<TokenId, Balance>,
+    allowed_marketplaces: UnorderedSet<AccountId>,
+    token_id_width: usize,
+    claim_codes: LookupMap<String, bool>,
+    max_approvals_per_token: u32,
+    burned_tokens: u64,
+    primary_split: Option<HashMap<AccountId, u32>>,
+    max_buys_per_block: Option<u32>,
+    buys_in_block: LookupMap<AccountId, (BlockHeight, u32)>,
+    consignments: UnorderedMap<TokenId, (AccountId, Balance)>,
+    consignment_commission_bps: u32,
+    max_redemptions: u32,
+    blocked_accounts: UnorderedSet<AccountId>,
+    minimal_metadata: bool,
+    payout_confirmation_required: bool,
+    pending_payouts: LookupMap<TokenId, (AccountId, HashMap<AccountId, Balance>)>,
+    flagged_payouts: Vector<FlaggedPayout>,
+    mint_end_ns: Option<u64>,
+    random_traits: Option<Vec<(String, u32)>>,
+    permit_keys: LookupMap<AccountId, (Vec<u8>, u64)>,
+    allowlist_nonces: LookupMap<AccountId, u64>,
+    refunds_in_progress: UnorderedSet<TokenId>,
+    redeemed_index: LookupMap<TokenId, bool>,
+    refunds_issued: Balance,
+    lock_transfer_after_redeem: bool,
+    burned_ticket_ids: LookupSet<TokenId>,
+    poaps_burned: u64,
+    ft_revenue: u128,
+}
+
+/// `Contract` as laid out before `max_supply` was split from the template's `copies`, read by
+/// `migrate` to upgrade a deployed contract.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct OldContract {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
     token_metadata: TokenMetadata,
     minted_tokens: u64,
     minting_price: u128,
-    perpetual_royalties: Option<HashMap<AccountId, u32>>
+    perpetual_royalties: Option<HashMap<AccountId, u32>>,
+    allowlist_signer: Option<Vec<u8>>,
+    total_revenue: u128,
+    redeemed_count: u64,
+    max_held_per_account: Option<u64>,
+    referral_bps: Option<u32>,
+    referral_earnings: LookupMap<AccountId, u128>,
+    tiers: UnorderedMap<String, Tier>,
+    transfer_fee: Option<u128>,
+    transfer_fee_on_plain_transfers: bool,
+    max_total_royalty_bps: u32,
+    event_info: EventInfo,
+    storage_balances: LookupMap<AccountId, Balance>,
+    redemption_grace_ns: u64,
+    transfer_cooldown_ns: Option<u64>,
+    minted_at: LookupMap<TokenId, u64>,
+    buy_requests: LookupMap<AccountId, Vec<(String, TokenId)>>,
+    metadata_frozen: bool,
+    payment_token: Option<(AccountId, u128)>,
+    hard_max_supply: u64,
+    promo_codes: LookupMap<String, PromoCode>,
+    transfer_memos: LookupMap<TokenId, String>,
+    transfer_history: LookupMap<TokenId, Vector<TransferRecord>>,
+    waitlist: Vector<(AccountId, Balance)>,
+    admins: UnorderedSet<AccountId>,
+    proceeds: Balance,
+    treasury_split: Option<HashMap<AccountId, u32>>,
+    poap_metadata: Option<TokenMetadata>,
+    poaps_minted: u64,
+    default_call_gas: u64,
+    redemptions_locked: bool,
+    refund_policy: Option<RefundPolicy>,
+    paid_prices: LookupMap<TokenId, Balance>,
+    allowed_marketplaces: UnorderedSet<AccountId>,
+    token_id_width: usize,
+    claim_codes: LookupMap<String, bool>,
+    max_approvals_per_token: u32,
+    burned_tokens: u64,
+    primary_split: Option<HashMap<AccountId, u32>>,
+    max_buys_per_block: Option<u32>,
+    buys_in_block: LookupMap<AccountId, (BlockHeight, u32)>,
+    consignments: UnorderedMap<TokenId, (AccountId, Balance)>,
+    consignment_commission_bps: u32,
+    max_redemptions: u32,
+    blocked_accounts: UnorderedSet<AccountId>,
+    minimal_metadata: bool,
+    payout_confirmation_required: bool,
+    pending_payouts: LookupMap<TokenId, (AccountId, HashMap<AccountId, Balance>)>,
+    flagged_payouts: Vector<FlaggedPayout>,
+    mint_end_ns: Option<u64>,
+    random_traits: Option<Vec<(String, u32)>>,
+    permit_keys: LookupMap<AccountId, (Vec<u8>, u64)>,
+    refunds_in_progress: UnorderedSet<TokenId>,
+    redeemed_index: LookupMap<TokenId, bool>,
+    refunds_issued: Balance,
+    lock_transfer_after_redeem: bool,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
 
+/// Attribute in a ticket's `extra` JSON that tracks whether it has been redeemed.
+const REDEEMED_TRAIT: &str = "redeemed";
+/// Attribute in a ticket's `extra` JSON marking complimentary tickets minted by `reserve_mint`.
+const COMP_TRAIT: &str = "comp";
+/// Attribute in a ticket's `extra` JSON marking tickets minted by `airdrop`.
+const AIRDROP_TRAIT: &str = "airdrop";
+/// Attribute in a ticket's `extra` JSON marking tickets claimed with a printed code.
+const CLAIM_CODE_TRAIT: &str = "claim_code";
+/// Attribute in a ticket's `extra` JSON naming the tier it was bought from.
+const TIER_TRAIT: &str = "tier";
+/// Attribute in a ticket's `extra` JSON holding the trait drawn for mystery-box tickets.
+const RANDOM_TRAIT: &str = "rarity";
+/// Attribute in a ticket's `extra` JSON holding the seat, when buyers pass one to `nft_buy`.
+const SEAT_TRAIT: &str = "seat";
+/// Attribute in a ticket's `extra` JSON holding the note a gift's buyer left for the recipient.
+const GIFT_MESSAGE_TRAIT: &str = "gift_message";
+/// Attribute in a ticket's `extra` JSON holding how often a multi-entry ticket can be redeemed.
+/// Tickets without it are single-use.
+const MAX_REDEMPTIONS_TRAIT: &str = "max_redemptions";
+/// Attribute in a ticket's `extra` JSON counting the redemptions of a multi-entry ticket.
+const REDEMPTIONS_USED_TRAIT: &str = "redemptions_used";
+/// Attributes only the contract writes, which buyers can't set through `extra_attributes`.
+const RESERVED_TRAITS: [&str; 9] = [
+    REDEEMED_TRAIT,
+    COMP_TRAIT,
+    AIRDROP_TRAIT,
+    CLAIM_CODE_TRAIT,
+    TIER_TRAIT,
+    RANDOM_TRAIT,
+    GIFT_MESSAGE_TRAIT,
+    MAX_REDEMPTIONS_TRAIT,
+    REDEMPTIONS_USED_TRAIT,
+];
+
+const TICKET_EVENT_STANDARD: &str = "ticket";
+const TICKET_EVENT_VERSION: &str = "1.0.0";
+
+/// Largest page any enumeration view returns, so no query can run out of gas.
+const MAX_LIMIT: u64 = 500;
+
+/// Upper bound on `redeem_many` batches, keeping a full batch well within the gas limit.
+const MAX_REDEEM_BATCH: usize = 25;
+
+/// How many of each caller's latest `nft_buy` request ids are remembered for retries.
+const MAX_BUY_REQUESTS_PER_ACCOUNT: usize = 10;
+
+/// Longest `nft_buy` request id accepted, in characters, bounding the storage buyers pay for.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Longest transfer memo kept for `last_transfer_memo`, in characters; longer memos are truncated.
+const MAX_STORED_MEMO_LEN: usize = 256;
+
+/// Longest `gift_message` `nft_buy` accepts, in characters.
+const MAX_GIFT_MESSAGE_LEN: usize = 280;
+
+/// Most `extra_attributes` `nft_buy` accepts, and the longest name or value of each, in
+/// characters. Together with the gift message they bound the ticket's `extra`.
+const MAX_EXTRA_ATTRIBUTES: usize = 8;
+const MAX_EXTRA_ATTRIBUTE_LEN: usize = 64;
+
+/// Upper bound on `airdrop` recipients per call, keeping a full batch within the gas limit.
+const MAX_AIRDROP_RECIPIENTS: usize = 50;
+
+/// Upper bound on the hashes `add_claim_codes` takes per call, keeping a full batch within the gas limit.
+const MAX_CLAIM_CODES_PER_CALL: usize = 100;
+
+/// Upper bound on the tokens `nft_approve_many` approves per call, keeping a full batch within the gas limit.
+const MAX_APPROVE_MANY: usize = 50;
+
+/// Id prefix of the attendance tokens minted on redemption, followed by the ticket's id.
+const POAP_TOKEN_PREFIX: &str = "poap-";
+
+/// Upper bound on the waitlist, so serving all of it after a supply increase stays within the gas limit.
+/// Each entry served shifts the rest of the list, so the cost grows with the square of the length:
+/// a full list of 20 takes about 100 Tgas of host calls alone, while 50 runs past the 300 Tgas limit.
+const MAX_WAITLIST_LEN: u64 = 20;
+
+/// Default cap on the accounts approved for one token, bounding the approval map's storage and
+/// the refund loop that clears it on every transfer.
+const DEFAULT_MAX_APPROVALS_PER_TOKEN: u32 = 8;
+
+/// Default cap on the sum of `perpetual_royalties`, leaving sellers at least half of each sale.
+const DEFAULT_MAX_TOTAL_ROYALTY_BPS: u32 = 5000;
+
+/// Storage the minimum `storage_deposit` pays for. It covers the registration, so it can't be
+/// spent or withdrawn and is only returned by `storage_unregister`.
+const MIN_STORAGE_BALANCE_BYTES: u64 = 1000;
+
+/// Optional capabilities built into this contract, reported by `contract_version` so front-ends
+/// can tell which features a deployment supports.
+const CONTRACT_FEATURES: &[&str] = &[
+    "tiers",
+    "ft_payment",
+    "allowlist",
+    "promo_codes",
+    "transfer_history",
+    "waitlist",
+    "poap",
+    "refunds",
+    "claim_codes",
+    "consignments",
+];
+
+const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_RESOLVE_REFUND: Gas = Gas(10_000_000_000_000);
+/// Prepaid gas `nft_buy` requires up front: enough for the mint with the largest allowed `extra`
+/// (`MAX_EXTRA_ATTRIBUTES` attributes of `MAX_EXTRA_ATTRIBUTE_LEN` characters plus a gift
+/// message) and the refund, referral and primary split transfers, so a buy never runs out
+/// halfway.
+const MIN_BUY_GAS: Gas = Gas(30_000_000_000_000);
+/// Gas reserved for the receiver's `nft_on_transfer` unless the owner or caller picks another
+/// amount. The receiver also gets its share of any unused gas.
+const DEFAULT_GAS_FOR_NFT_ON_TRANSFER: Gas = Gas(25_000_000_000_000);
+
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
     NonFungibleToken,
@@ -58,6 +459,30 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    ReferralEarnings,
+    Tiers,
+    StorageBalances,
+    MintedAt,
+    BuyRequests,
+    PromoCodes,
+    TransferMemos,
+    TransferHistory,
+    TransferHistoryEntries { token_id_hash: [u8; 32] },
+    Waitlist,
+    Admins,
+    PaidPrices,
+    AllowedMarketplaces,
+    ClaimCodes,
+    BuysInBlock,
+    Consignments,
+    BlockedAccounts,
+    PendingPayouts,
+    FlaggedPayouts,
+    PermitKeys,
+    RefundsInProgress,
+    RedeemedIndex,
+    BurnedTicketIds,
+    AllowlistNonces,
 }
 
 #[near_bindgen]
@@ -92,14 +517,30 @@ impl Contract {
                 reference_hash: None
             },
             U128::from(10u128.pow(24)),
-            None
+            None,
+            EventInfo {
+                venue: "Paradise Arena".to_string(),
+                event_date_ns: 0,
+                doors_open_ns: None,
+                city: None,
+            },
+            1000,
+            false
         )
     }
 
+    /// With `pad_token_ids`, ticket ids are zero-padded to the number of digits of
+    /// `hard_max_supply`, e.g. "0001" for a ceiling of 1000, so they sort the same as strings
+    /// and as numbers. The width is fixed at init, as supply can grow up to the ceiling.
+    ///
+    /// The template's `copies` sets the starting `max_supply`; after that the two are independent.
     #[init]
-    pub fn new(owner_id: AccountId, metadata: NFTContractMetadata, token_metadata: TokenMetadata, minting_price: U128, perpetual_royalties: Option<HashMap<AccountId, u32>>) -> Self {
-        assert!(!env::state_exists(), "Already initialized");
+    pub fn new(owner_id: AccountId, metadata: NFTContractMetadata, token_metadata: TokenMetadata, minting_price: U128, perpetual_royalties: Option<HashMap<AccountId, u32>>, event_info: EventInfo, hard_max_supply: u64, pad_token_ids: bool) -> Self {
+        assert!(!env::state_exists(), "{}", ERR_ALREADY_INITIALIZED);
         metadata.assert_valid();
+        assert!(token_metadata.copies.unwrap_or(0) > 0, "{}", ERR_ZERO_COPIES);
+        assert!(token_metadata.copies.unwrap() <= hard_max_supply, "{}", ERR_EXCEEDS_HARD_SUPPLY);
+        assert_royalties_within_cap(&perpetual_royalties, DEFAULT_MAX_TOTAL_ROYALTY_BPS);
         Self {
             tokens: NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
@@ -109,403 +550,8259 @@ impl Contract {
                 Some(StorageKey::Approval),
             ),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+            max_supply: token_metadata.copies.unwrap(),
             token_metadata,
             minted_tokens: 0,
             minting_price: minting_price.0,
-            perpetual_royalties: perpetual_royalties,
+            perpetual_royalties,
+            allowlist_signer: None,
+            total_revenue: 0,
+            redeemed_count: 0,
+            max_held_per_account: None,
+            referral_bps: None,
+            referral_earnings: LookupMap::new(StorageKey::ReferralEarnings),
+            tiers: UnorderedMap::new(StorageKey::Tiers),
+            transfer_fee: None,
+            transfer_fee_on_plain_transfers: false,
+            max_total_royalty_bps: DEFAULT_MAX_TOTAL_ROYALTY_BPS,
+            event_info,
+            storage_balances: LookupMap::new(StorageKey::StorageBalances),
+            redemption_grace_ns: 0,
+            transfer_cooldown_ns: None,
+            minted_at: LookupMap::new(StorageKey::MintedAt),
+            buy_requests: LookupMap::new(StorageKey::BuyRequests),
+            metadata_frozen: false,
+            payment_token: None,
+            hard_max_supply,
+            promo_codes: LookupMap::new(StorageKey::PromoCodes),
+            transfer_memos: LookupMap::new(StorageKey::TransferMemos),
+            transfer_history: LookupMap::new(StorageKey::TransferHistory),
+            waitlist: Vector::new(StorageKey::Waitlist),
+            admins: UnorderedSet::new(StorageKey::Admins),
+            proceeds: 0,
+            treasury_split: None,
+            poap_metadata: None,
+            poaps_minted: 0,
+            default_call_gas: DEFAULT_GAS_FOR_NFT_ON_TRANSFER.0,
+            redemptions_locked: false,
+            refund_policy: None,
+            paid_prices: LookupMap::new(StorageKey::PaidPrices),
+            allowed_marketplaces: UnorderedSet::new(StorageKey::AllowedMarketplaces),
+            token_id_width: if pad_token_ids { hard_max_supply.to_string().len() } else { 0 },
+            claim_codes: LookupMap::new(StorageKey::ClaimCodes),
+            max_approvals_per_token: DEFAULT_MAX_APPROVALS_PER_TOKEN,
+            burned_tokens: 0,
+            primary_split: None,
+            max_buys_per_block: None,
+            buys_in_block: LookupMap::new(StorageKey::BuysInBlock),
+            consignments: UnorderedMap::new(StorageKey::Consignments),
+            consignment_commission_bps: 0,
+            max_redemptions: 1,
+            blocked_accounts: UnorderedSet::new(StorageKey::BlockedAccounts),
+            minimal_metadata: false,
+            payout_confirmation_required: false,
+            pending_payouts: LookupMap::new(StorageKey::PendingPayouts),
+            flagged_payouts: Vector::new(StorageKey::FlaggedPayouts),
+            mint_end_ns: None,
+            random_traits: None,
+            permit_keys: LookupMap::new(StorageKey::PermitKeys),
+            allowlist_nonces: LookupMap::new(StorageKey::AllowlistNonces),
+            refunds_in_progress: UnorderedSet::new(StorageKey::RefundsInProgress),
+            redeemed_index: LookupMap::new(StorageKey::RedeemedIndex),
+            refunds_issued: 0,
+            lock_transfer_after_redeem: false,
+            burned_ticket_ids: LookupSet::new(StorageKey::BurnedTicketIds),
+            poaps_burned: 0,
+            ft_revenue: 0,
+        }
+    }
+
+    /// Upgrades state written before `max_supply` was tracked on its own. The old mint cap was the
+    /// template's `copies`, so that becomes `max_supply`. Fields added since start out empty:
+    /// `ft_revenue` and `poaps_burned` only count from the upgrade on.
+    #[init(ignore_state)]
+    #[private]
+    pub fn migrate() -> Self {
+        let old: OldContract = env::state_read().expect(ERR_NO_STATE_TO_MIGRATE);
+        let max_supply = old.token_metadata.copies.unwrap_or(old.hard_max_supply);
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            token_metadata: old.token_metadata,
+            minted_tokens: old.minted_tokens,
+            max_supply,
+            minting_price: old.minting_price,
+            perpetual_royalties: old.perpetual_royalties,
+            allowlist_signer: old.allowlist_signer,
+            total_revenue: old.total_revenue,
+            redeemed_count: old.redeemed_count,
+            max_held_per_account: old.max_held_per_account,
+            referral_bps: old.referral_bps,
+            referral_earnings: old.referral_earnings,
+            tiers: old.tiers,
+            transfer_fee: old.transfer_fee,
+            transfer_fee_on_plain_transfers: old.transfer_fee_on_plain_transfers,
+            max_total_royalty_bps: old.max_total_royalty_bps,
+            event_info: old.event_info,
+            storage_balances: old.storage_balances,
+            redemption_grace_ns: old.redemption_grace_ns,
+            transfer_cooldown_ns: old.transfer_cooldown_ns,
+            minted_at: old.minted_at,
+            buy_requests: old.buy_requests,
+            metadata_frozen: old.metadata_frozen,
+            payment_token: old.payment_token,
+            hard_max_supply: old.hard_max_supply,
+            promo_codes: old.promo_codes,
+            transfer_memos: old.transfer_memos,
+            transfer_history: old.transfer_history,
+            waitlist: old.waitlist,
+            admins: old.admins,
+            proceeds: old.proceeds,
+            treasury_split: old.treasury_split,
+            poap_metadata: old.poap_metadata,
+            poaps_minted: old.poaps_minted,
+            default_call_gas: old.default_call_gas,
+            redemptions_locked: old.redemptions_locked,
+            refund_policy: old.refund_policy,
+            paid_prices: old.paid_prices,
+            allowed_marketplaces: old.allowed_marketplaces,
+            token_id_width: old.token_id_width,
+            claim_codes: old.claim_codes,
+            max_approvals_per_token: old.max_approvals_per_token,
+            burned_tokens: old.burned_tokens,
+            primary_split: old.primary_split,
+            max_buys_per_block: old.max_buys_per_block,
+            buys_in_block: old.buys_in_block,
+            consignments: old.consignments,
+            consignment_commission_bps: old.consignment_commission_bps,
+            max_redemptions: old.max_redemptions,
+            blocked_accounts: old.blocked_accounts,
+            minimal_metadata: old.minimal_metadata,
+            payout_confirmation_required: old.payout_confirmation_required,
+            pending_payouts: old.pending_payouts,
+            flagged_payouts: old.flagged_payouts,
+            mint_end_ns: old.mint_end_ns,
+            random_traits: old.random_traits,
+            permit_keys: old.permit_keys,
+            allowlist_nonces: LookupMap::new(StorageKey::AllowlistNonces),
+            refunds_in_progress: old.refunds_in_progress,
+            redeemed_index: old.redeemed_index,
+            refunds_issued: old.refunds_issued,
+            lock_transfer_after_redeem: old.lock_transfer_after_redeem,
+            burned_ticket_ids: LookupSet::new(StorageKey::BurnedTicketIds),
+            poaps_burned: 0,
+            ft_revenue: 0,
         }
     }
 
+    /// Mints a token with an explicit `token_id` without charging the minting price.
+    /// Only the contract owner can call this, so buyers can't bypass `nft_buy`. Positive numeric
+    /// ids are reserved for the sequential tickets, so a custom id never collides with a later buy.
+    #[payable]
+    pub fn nft_mint(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        token_metadata: TokenMetadata,
+    ) -> Token {
+        self.assert_owner();
+        assert!(!is_sequential_token_id(&token_id), "{}", ERR_SEQUENTIAL_TOKEN_ID);
+        assert!(self.minted_tokens < self.max_supply, "{}", ERR_SOLD_OUT);
+        self.minted_tokens += 1;
+        self.assert_supply_invariant();
+
+        self.tokens.internal_mint(token_id, receiver_id, Some(token_metadata))
+    }
+
+    /// Buys the next ticket at the minting price, discounted by `promo_code` if given. A
+    /// client-chosen `request_id` makes retries safe: if the caller already bought with the same
+    /// id, the deposit is refunded and the ticket from that purchase is returned. Ids are per
+    /// caller and only the latest `MAX_BUY_REQUESTS_PER_ACCOUNT` (10) are remembered.
+    ///
+    /// `valid_from_ns`/`valid_until_ns` replace the template's `starts_at`/`expires_at` for just
+    /// this ticket, e.g. a single-day pass to a multi-day festival.
+    ///
+    /// When buying for someone else, `gift_message` is kept in the ticket's `gift_message`
+    /// attribute and sent as the mint event's memo. It's ignored when buying for yourself.
+    ///
+    /// Calls with less than `min_buy_gas` prepaid gas are rejected before anything changes.
     #[payable]
     pub fn nft_buy(
         &mut self,
-        receiver_id: Option<AccountId>
+        receiver_id: Option<AccountId>,
+        extra_attributes: Option<Vec<(String, String)>>,
+        referrer: Option<AccountId>,
+        request_id: Option<String>,
+        promo_code: Option<String>,
+        valid_from_ns: Option<u64>,
+        valid_until_ns: Option<u64>,
+        gift_message: Option<String>,
     ) -> Token {
+        assert!(env::prepaid_gas() >= MIN_BUY_GAS, "{}", fill(ERR_BUY_GAS, &[&MIN_BUY_GAS.0]));
         let caller_id = env::predecessor_account_id();
+        if let Some(request_id) = &request_id {
+            assert!(
+                request_id.chars().count() <= MAX_REQUEST_ID_LEN,
+                "{}",
+                fill(ERR_REQUEST_ID_TOO_LONG, &[&MAX_REQUEST_ID_LEN])
+            );
+            let buy_requests = self.buy_requests.get(&caller_id).unwrap_or_default();
+            if let Some((_, token_id)) = buy_requests.iter().find(|(seen_id, _)| seen_id == request_id) {
+                let token = self
+                    .tokens
+                    .nft_token(token_id.clone())
+                    .expect(ERR_BUY_REQUEST_TOKEN_GONE);
+                if env::attached_deposit() > 0 {
+                    Promise::new(caller_id).transfer(env::attached_deposit());
+                }
+                return token;
+            }
+        }
+
         let receiver_id_final = if let Some(receiver_id) = receiver_id {
             receiver_id
         } else {
-            caller_id
+            caller_id.clone()
         };
-        let attached_deposit = env::attached_deposit();
-        assert!(attached_deposit >= self.minting_price);
-
-
-        assert!(self.minted_tokens < self.token_metadata.copies.unwrap(), "Error: Sold out");
-        let token_id = self.minted_tokens + 1;
-        self.minted_tokens += 1;
+        if let Some(referrer) = &referrer {
+            assert!(referrer != &receiver_id_final && referrer != &caller_id, "{}", ERR_SELF_REFERRAL);
+        }
+        if let Some(extra_attributes) = &extra_attributes {
+            assert!(
+                extra_attributes.len() <= MAX_EXTRA_ATTRIBUTES,
+                "{}",
+                fill(ERR_TOO_MANY_EXTRA_ATTRIBUTES, &[&MAX_EXTRA_ATTRIBUTES])
+            );
+            assert!(
+                extra_attributes.iter().all(|(trait_type, value)| {
+                    trait_type.chars().count() <= MAX_EXTRA_ATTRIBUTE_LEN
+                        && value.chars().count() <= MAX_EXTRA_ATTRIBUTE_LEN
+                }),
+                "{}",
+                fill(ERR_EXTRA_ATTRIBUTE_TOO_LONG, &[&MAX_EXTRA_ATTRIBUTE_LEN])
+            );
+        }
+        if let (Some(valid_from_ns), Some(valid_until_ns)) = (valid_from_ns, valid_until_ns) {
+            assert!(valid_from_ns < valid_until_ns, "{}", ERR_INVALID_VALIDITY_WINDOW);
+        }
+        let mut contract_attributes = vec![];
+        if let Some(gift_message) = gift_message.filter(|_| receiver_id_final != caller_id) {
+            assert!(
+                gift_message.chars().count() <= MAX_GIFT_MESSAGE_LEN,
+                "{}",
+                fill(ERR_GIFT_MESSAGE_TOO_LONG, &[&MAX_GIFT_MESSAGE_LEN])
+            );
+            contract_attributes.push((GIFT_MESSAGE_TRAIT.to_string(), gift_message));
+        }
 
-        self.tokens.internal_mint(token_id.to_string(), receiver_id_final, Some(
-                TokenMetadata { 
-                    title:  self.token_metadata.title.clone(), 
-                    description: self.token_metadata.description.clone(), 
-                    media: self.token_metadata.media.clone(), 
-                    media_hash: self.token_metadata.media_hash.clone(), 
-                    copies: self.token_metadata.copies, 
-                    issued_at: self.token_metadata.issued_at.clone(), 
-                    expires_at: self.token_metadata.expires_at.clone(), 
-                    starts_at: self.token_metadata.starts_at.clone(), 
-                    updated_at: self.token_metadata.updated_at.clone(), 
-                    extra: Some(json!({"attributes": [{"trait_type": "redeemed", "value": "false"}]}).to_string()),
-                    reference: self.token_metadata.reference.clone(), 
-                    reference_hash: self.token_metadata.reference_hash.clone() 
-                }
-            )
+        let price = match promo_code {
+            Some(promo_code) => self.use_promo_code(&promo_code),
+            None => self.minting_price,
+        };
+        self.internal_buy(
+            receiver_id_final,
+            extra_attributes,
+            contract_attributes,
+            price,
+            (valid_from_ns, valid_until_ns),
+            (request_id, referrer, None),
         )
     }
 
+    /// Returns the prepaid gas `nft_buy` requires, for clients setting their gas limit.
+    pub fn min_buy_gas(&self) -> Gas {
+        MIN_BUY_GAS
+    }
+
+    /// Buys a ticket during an allowlisted sale. `signature` is the allowlist signer's ed25519
+    /// signature over `"{receiver_id}:{nonce}:{expiry_ns}:{contract_account_id}"`, so a proof
+    /// can't be used for another account, after `expiry_ns`, or on another event's contract.
+    /// Each proof buys one ticket: its `nonce` must be above the receiver's last one, whose
+    /// storage the buyer pays for.
     #[payable]
-    pub fn redeem_nft(
+    pub fn nft_buy_signed(
         &mut self,
-        token_id: TokenId
+        receiver_id: AccountId,
+        signature: Vec<u8>,
+        nonce: u64,
+        expiry_ns: u64
     ) -> Token {
-        assert_one_yocto();
-        let caller_id = env::predecessor_account_id();
+        let public_key = self.allowlist_signer.as_ref().expect(ERR_ALLOWLIST_SIGNER_NOT_SET);
+        assert!(env::block_timestamp() <= expiry_ns, "{}", ERR_SIGNATURE_EXPIRED);
+        assert!(nonce > self.allowlist_nonce(receiver_id.clone()), "{}", ERR_ALLOWLIST_NONCE_USED);
 
-        // let token_metadata = self.tokens.token_metadata_by_id.unwrap().get(&token_id).unwrap();
-        let mut token = self.nft_token(token_id.clone()).unwrap();
-        let mut token_metadata = token.metadata.as_mut().unwrap();
-
-        assert_eq!(token.owner_id, caller_id, "Error: Token not owned by the caller");
+        let message = format!("{}:{}:{}:{}", receiver_id, nonce, expiry_ns, env::current_account_id());
+        assert!(
+            verify_ed25519(public_key, message.as_bytes(), &signature),
+            "{}",
+            ERR_INVALID_SIGNATURE
+        );
 
-        assert_eq!(token_metadata.extra, Some(json!({"attributes": [{"trait_type": "redeemed", "value": "false"}]}).to_string()));
-        token_metadata.extra = Some(json!({"attributes": [{"trait_type": "redeemed", "value": "true"}]}).to_string());
+        self.internal_buy(receiver_id, None, vec![], self.minting_price, (None, None), (None, None, Some(nonce)))
+    }
 
-        self.tokens.token_metadata_by_id.as_mut().unwrap().insert(&token_id, &token_metadata);
+    /// Returns the last allowlist nonce `nft_buy_signed` used for the account, which the next
+    /// proof must exceed, or 0 if it never bought with one.
+    pub fn allowlist_nonce(&self, account_id: AccountId) -> u64 {
+        self.allowlist_nonces.get(&account_id).unwrap_or(0)
+    }
 
-        token
+    /// Sets the ed25519 public key whose signatures `nft_buy_signed` accepts, e.g. to rotate a
+    /// compromised key. Signatures from the previous key stop working immediately.
+    #[payable]
+    pub fn set_allowlist_signer(&mut self, public_key: Vec<u8>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert_eq!(public_key.len(), 32, "{}", ERR_ALLOWLIST_SIGNER_LENGTH);
+        let rotated = self.allowlist_signer.replace(public_key).is_some();
+        env::log_str(if rotated { "Allowlist signer rotated" } else { "Allowlist signer set" });
     }
 
-    pub fn tokens_left(&self) -> u64 {
-        self.token_metadata.copies.unwrap() - self.minted_tokens
+    /// Enables the referral program, paying referrers `referral_bps` of the minting price for
+    /// each purchase they bring in, or disables it with `None`.
+    #[payable]
+    pub fn set_referral_bps(&mut self, referral_bps: Option<u32>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(referral_bps) = referral_bps {
+            assert!(referral_bps <= 10000, "{}", ERR_REFERRAL_BPS_TOO_HIGH);
+        }
+        assert_primary_shares_within_price(&self.primary_split, referral_bps);
+        self.referral_bps = referral_bps;
     }
 
-    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: u32) -> Payout {
-		let token = self.tokens.nft_token(token_id).expect("Error: No token_id found");
+    /// Replaces the perpetual royalties paid on resales through `nft_payout` and
+    /// `nft_transfer_payout`, or removes them with `None`. They never apply to primary sales,
+    /// whose revenue stays on the contract apart from `primary_split`. Their sum can't exceed
+    /// `max_total_royalty_bps`.
+    #[payable]
+    pub fn set_royalties(&mut self, perpetual_royalties: Option<HashMap<AccountId, u32>>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert_royalties_within_cap(&perpetual_royalties, self.max_total_royalty_bps);
+        self.perpetual_royalties = perpetual_royalties;
+    }
 
-        let owner_id = token.owner_id;
-        let mut total_perpetual = 0;
-        let balance_u128 = u128::from(balance);
-        let mut payout_object = Payout {
-            payout: HashMap::new()
-        };
+    /// Changes the cap on the sum of perpetual royalties. It can't go below the royalties
+    /// already configured.
+    #[payable]
+    pub fn set_max_total_royalty_bps(&mut self, max_total_royalty_bps: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(max_total_royalty_bps <= 10000, "{}", ERR_ROYALTY_CAP_TOO_HIGH);
+        assert_royalties_within_cap(&self.perpetual_royalties, max_total_royalty_bps);
+        self.max_total_royalty_bps = max_total_royalty_bps;
+    }
 
-        if let Some(royalties) = &self.perpetual_royalties {
-		    assert!(royalties.len() as u32 <= max_len_payout, "Market cannot payout to that many receivers");
+    /// Returns the most the perpetual royalties can take from a sale, in basis points.
+    pub fn max_total_royalty_bps(&self) -> u32 {
+        self.max_total_royalty_bps
+    }
 
-		    for (k, v) in royalties.iter() {
-		    	let key = k.clone();
-		    	if key != owner_id {
-                    //
-		    		payout_object.payout.insert(key, royalty_to_payout(*v, balance_u128));
-		    		total_perpetual += *v;
-		    	}
-		    }
-        }
+    /// Adds or replaces a promo code taking `discount_bps` off the minting price, usable at most
+    /// `max_uses` times, or without limit with `None`.
+    #[payable]
+    pub fn add_promo_code(&mut self, code: String, discount_bps: u32, max_uses: Option<u64>) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(discount_bps <= 10000, "{}", ERR_DISCOUNT_TOO_HIGH);
+        self.promo_codes.insert(&code, &PromoCode { discount_bps, max_uses, uses: 0 });
+    }
 
-		payout_object.payout.insert(owner_id, royalty_to_payout(10000 - total_perpetual, balance_u128));
+    #[payable]
+    pub fn remove_promo_code(&mut self, code: String) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.promo_codes.remove(&code);
+    }
 
-		payout_object
-	}
+    /// Returns the promo code's discount and how often it has been used.
+    pub fn promo_code(&self, code: String) -> Option<PromoCode> {
+        self.promo_codes.get(&code)
+    }
 
-    //transfers the token to the receiver ID and returns the payout object that should be payed given the passed in balance. 
+    /// Registers the codes printed on physical tickets by their hex-encoded sha256 hashes, so the
+    /// plaintext codes never appear on-chain. Codes that are already registered keep their state.
     #[payable]
-    pub fn nft_transfer_payout(
-        &mut self,
-        receiver_id: AccountId,
-        token_id: TokenId,
-        approval_id: u64,
-        memo: Option<String>,
-        balance: U128,
-        max_len_payout: u32,
-    ) -> Payout { 
+    pub fn add_claim_codes(&mut self, hashed: Vec<String>) {
         assert_one_yocto();
-        let sender_id = env::predecessor_account_id();
-        let (owner_id, approved_account_ids) = self.tokens.internal_transfer(
-            &sender_id,
-            &receiver_id,
-            &token_id,
-            Some(approval_id),
-            memo,
+        self.assert_owner();
+        assert!(
+            hashed.len() <= MAX_CLAIM_CODES_PER_CALL,
+            "{}",
+            fill(ERR_TOO_MANY_CLAIM_CODES, &[&MAX_CLAIM_CODES_PER_CALL])
         );
-
-        if let Some(approved_account_ids) = approved_account_ids {
-            refund_approved_account_ids(
-                owner_id.clone(),
-                &approved_account_ids,
+        for hash in hashed {
+            assert!(
+                hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)),
+                "{}",
+                ERR_CLAIM_CODE_NOT_HASHED
             );
+            if !self.claim_codes.contains_key(&hash) {
+                self.claim_codes.insert(&hash, &false);
+            }
         }
+    }
 
-        let mut total_perpetual = 0;
-        let balance_u128 = u128::from(balance);
-        let mut payout_object = Payout {
-            payout: HashMap::new()
-        };
-
-        if let Some(royalties) = &self.perpetual_royalties {
-		    assert!(royalties.len() as u32 <= max_len_payout, "Market cannot payout to that many receivers");
+    /// Mints a ticket to `receiver_id` in exchange for a code printed on a physical ticket, marked
+    /// with a `claim_code` attribute. Each code works once. The ticket was paid for off-chain, so
+    /// the caller only covers its storage.
+    #[payable]
+    pub fn claim_by_code(&mut self, code: String, receiver_id: AccountId) -> Token {
+        let hash = hash_claim_code(&code);
+        let claimed = self.claim_codes.get(&hash).expect(ERR_UNKNOWN_CLAIM_CODE);
+        assert!(!claimed, "{}", ERR_CODE_ALREADY_CLAIMED);
+        self.claim_codes.insert(&hash, &true);
+        self.internal_mint_free(vec![receiver_id], CLAIM_CODE_TRAIT).remove(0)
+    }
 
-		    for (k, v) in royalties.iter() {
-		    	let key = k.clone();
-		    	if key != owner_id {
-		    		payout_object.payout.insert(key, royalty_to_payout(*v, balance_u128));
-		    		total_perpetual += *v;
-		    	}
-		    }
-        }
+    /// Returns whether the code hashing to `hashed` has been claimed, or `None` if it isn't registered.
+    pub fn claim_code_status(&self, hashed: String) -> Option<bool> {
+        self.claim_codes.get(&hashed)
+    }
 
-		payout_object.payout.insert(owner_id, royalty_to_payout(10000 - total_perpetual, balance_u128));
+    /// Returns the total referral rewards paid out to `account_id`.
+    pub fn referral_earnings(&self, account_id: AccountId) -> U128 {
+        U128(self.referral_earnings.get(&account_id).unwrap_or(0))
+    }
 
-		payout_object
+    /// Mints `count` complimentary tickets to `receiver_id` without charging the minting price,
+    /// e.g. for press and VIP holds. They count towards the supply like sold tickets and are
+    /// marked with a `comp` attribute. The owner covers their storage.
+    #[payable]
+    pub fn reserve_mint(&mut self, count: u16, receiver_id: AccountId) -> Vec<Token> {
+        self.assert_admin_or_owner();
+        self.internal_mint_free(vec![receiver_id; count as usize], COMP_TRAIT)
     }
-}
 
-fn royalty_to_payout(royalty_percentage: u32, amount_to_pay: u128) -> U128 {
-    U128(royalty_percentage as u128 * amount_to_pay / 10_000u128)
-}
+    /// Reissues the ticket id freed by a burn or refund to `receiver_id`, e.g. to correct a
+    /// mistaken refund. Only ids of burned sequential tickets can be reissued, so the next
+    /// `nft_buy` still gets the id after `minted_tokens`. The reissued ticket no longer counts as
+    /// burned, and the owner covers its storage.
+    #[payable]
+    pub fn mint_specific(&mut self, token_id: TokenId, receiver_id: AccountId) -> Token {
+        self.assert_owner();
+        assert!(!self.tokens.owner_by_id.contains_key(&token_id), "{}", ERR_TOKEN_ID_EXISTS);
 
-fn refund_approved_account_ids_iter<'a, I>(
-    account_id: AccountId,
-    approved_account_ids: I, //the approved account IDs must be passed in as an iterator
-) -> Promise
-where
-    I: Iterator<Item = &'a AccountId>,
-{
-    //get the storage total by going through and summing all the bytes for each approved account IDs
-    let storage_released: u64 = approved_account_ids.map(bytes_for_approved_account_id).sum();
-    //transfer the account the storage that is released
-    Promise::new(account_id).transfer(Balance::from(storage_released) * env::storage_byte_cost())
-}
+        let initial_storage_usage = env::storage_usage();
+        let token =
+            self.internal_reissue_ticket(token_id, receiver_id, vec![(REDEEMED_TRAIT.to_string(), "false".to_string())]);
+        refund_unused_deposit(0, initial_storage_usage);
 
-fn refund_approved_account_ids(
-    account_id: AccountId,
-    approved_account_ids: &HashMap<AccountId, u64>,
-) -> Promise {
-    //call the refund_approved_account_ids_iter with the approved account IDs as keys
-    refund_approved_account_ids_iter(account_id, approved_account_ids.keys())
-}
+        NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: None }.emit();
+        token
+    }
+
+    /// Mints one free ticket to each of `recipients`, e.g. for sponsors before launch, marked
+    /// with an `airdrop` attribute. At most `MAX_AIRDROP_RECIPIENTS` (50) recipients per call
+    /// to stay within the gas limit. The owner covers their storage.
+    #[payable]
+    pub fn airdrop(&mut self, recipients: Vec<AccountId>) -> Vec<Token> {
+        self.assert_admin_or_owner();
+        assert!(
+            recipients.len() <= MAX_AIRDROP_RECIPIENTS,
+            "{}",
+            fill(ERR_TOO_MANY_AIRDROP_RECIPIENTS, &[&MAX_AIRDROP_RECIPIENTS])
+        );
+        self.internal_mint_free(recipients, AIRDROP_TRAIT)
+    }
 
+    /// Sets a flat fee collected by the contract on secondary transfers through `nft_transfer_payout`,
+    /// and also on `nft_transfer`/`nft_transfer_call` when `include_plain_transfers` is set. Unlike
+    /// royalties, which are a share of the sale price, this fee is the same for every transfer.
+    #[payable]
+    pub fn set_transfer_fee(&mut self, transfer_fee: Option<U128>, include_plain_transfers: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.transfer_fee = transfer_fee.map(|fee| fee.0);
+        self.transfer_fee_on_plain_transfers = include_plain_transfers;
+    }
 
-near_contract_standards::impl_non_fungible_token_core!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
+    pub fn transfer_fee(&self) -> Option<U128> {
+        self.transfer_fee.map(U128)
+    }
 
-#[near_bindgen]
-impl NonFungibleTokenMetadataProvider for Contract {
-    fn nft_metadata(&self) -> NFTContractMetadata {
-        self.metadata.get().unwrap()
+    /// Returns the NEAR collected from ticket sales, minus the refunds paid out. Unlike the
+    /// account balance, this excludes storage deposits and refunds in flight.
+    pub fn total_revenue(&self) -> U128 {
+        U128(self.total_revenue)
     }
-}
 
-#[cfg(all(test, not(target_arch = "wasm32")))]
-mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::testing_env;
-    use std::collections::HashMap;
+    /// Returns the amount of `payment_token` collected from ticket sales, kept apart from
+    /// `total_revenue` since it's a different currency.
+    pub fn ft_revenue(&self) -> U128 {
+        U128(self.ft_revenue)
+    }
 
-    use super::*;
+    /// Returns the NEAR earned from sales and transfer fees, after referral rewards, that hasn't
+    /// been withdrawn yet.
+    pub fn proceeds(&self) -> U128 {
+        U128(self.proceeds)
+    }
 
-    const MINT_STORAGE_COST: u128 = 5870000000000000000000;
+    /// Sends `amount` of the proceeds, or all of them, to the treasury split if one is set and
+    /// to the owner otherwise. The split's rounding dust goes to the owner.
+    #[payable]
+    pub fn withdraw(&mut self, amount: Option<U128>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let amount = amount.map_or(self.proceeds, |amount| amount.0);
+        assert!(
+            amount <= self.proceeds,
+            "{}",
+            fill(ERR_WITHDRAW_ABOVE_PROCEEDS, &[&self.proceeds])
+        );
+        self.proceeds -= amount;
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
+        let mut owner_share = amount;
+        for (account_id, bps) in self.treasury_split.iter().flatten() {
+            let share = royalty_to_payout(*bps, amount).0;
+            owner_share -= share;
+            if share > 0 {
+                Promise::new(account_id.clone()).transfer(share);
+            }
+        }
+        if owner_share > 0 {
+            Promise::new(self.tokens.owner_id.clone()).transfer(owner_share);
+        }
     }
 
-    fn sample_token_metadata() -> TokenMetadata {
-        TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
-            media: None,
-            media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
-            starts_at: None,
-            updated_at: None,
-            extra: None,
-            reference: None,
-            reference_hash: None,
+    /// Splits future withdrawals between partners, in basis points adding up to 10000. `None`
+    /// sends withdrawals to the owner again.
+    #[payable]
+    pub fn set_treasury_split(&mut self, treasury_split: Option<HashMap<AccountId, u32>>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(treasury_split) = &treasury_split {
+            assert_eq!(
+                treasury_split.values().sum::<u32>(),
+                10000,
+                "{}",
+                ERR_TREASURY_SPLIT_TOTAL
+            );
         }
+        self.treasury_split = treasury_split;
     }
 
-    #[test]
-    fn test_new() {
-        let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into());
-        testing_env!(context.is_view(true).build());
-        assert_eq!(contract.nft_token("1".to_string()), None);
+    pub fn treasury_split(&self) -> Option<HashMap<AccountId, u32>> {
+        self.treasury_split.clone()
     }
 
-    #[test]
-    #[should_panic(expected = "The contract is not initialized")]
-    fn test_default() {
-        let context = get_context(accounts(1));
-        testing_env!(context.build());
-        let _contract = Contract::default();
+    /// Shares each primary sale paid in NEAR with partners, in basis points of the price, paid
+    /// out as the ticket is bought. The rest stays in the proceeds. Together with the referral
+    /// share it can't exceed the price. `None` stops sharing primary sales.
+    #[payable]
+    pub fn set_primary_split(&mut self, primary_split: Option<HashMap<AccountId, u32>>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert_primary_shares_within_price(&primary_split, self.referral_bps);
+        self.primary_split = primary_split;
     }
 
-    #[test]
-    fn test_mint() {
-        let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+    pub fn primary_split(&self) -> Option<HashMap<AccountId, u32>> {
+        self.primary_split.clone()
+    }
+
+    /// Caps how many tickets one account can buy within a single block, to slow down bots
+    /// sweeping the supply, or lifts the cap with `None`.
+    #[payable]
+    pub fn set_max_buys_per_block(&mut self, max_buys_per_block: Option<u32>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(max_buys_per_block) = max_buys_per_block {
+            assert!(max_buys_per_block > 0, "{}", ERR_ZERO_BUYS_PER_BLOCK);
+        }
+        self.max_buys_per_block = max_buys_per_block;
+    }
+
+    pub fn max_buys_per_block(&self) -> Option<u32> {
+        self.max_buys_per_block
+    }
+
+    /// Stops new tickets from being minted from `mint_end_ns` on, e.g. an hour before doors, or
+    /// lifts the cutoff with `None`. Every mint checks it: the `nft_buy` methods, token payments,
+    /// `reserve_mint`, `airdrop`, claim codes and serving the waitlist. Transfers, resales and
+    /// redemptions of existing tickets are unaffected.
+    #[payable]
+    pub fn set_mint_end_ns(&mut self, mint_end_ns: Option<u64>) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.mint_end_ns = mint_end_ns;
+    }
+
+    pub fn mint_end_ns(&self) -> Option<u64> {
+        self.mint_end_ns
+    }
+
+    /// Adds a ticket tier, e.g. "VIP", with its own price and supply. Tier tickets still count
+    /// towards the contract-wide `max_supply`.
+    #[payable]
+    pub fn add_tier(&mut self, tier: String, price: U128, max_supply: u64) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(self.tiers.get(&tier).is_none(), "{}", ERR_TIER_EXISTS);
+        let tiers_supply: u64 = self.tiers.values().map(|tier| tier.max_supply).sum();
+        assert!(tiers_supply + max_supply <= self.hard_max_supply, "{}", ERR_EXCEEDS_HARD_SUPPLY);
+        self.tiers.insert(&tier, &Tier { price: price.0, max_supply, minted: 0, redeemed: 0, metadata: None });
+        self.assert_supply_invariant();
+    }
+
+    /// Buys a ticket from `tier` at the tier's price. The tier is recorded in the token's attributes.
+    #[payable]
+    pub fn nft_buy_tier(&mut self, tier: String, receiver_id: Option<AccountId>) -> Token {
+        let mut tier_state = self.tiers.get(&tier).expect(ERR_TIER_NOT_FOUND);
+        assert!(tier_state.minted < tier_state.max_supply, "{}", ERR_TIER_SOLD_OUT);
+        tier_state.minted += 1;
+        self.tiers.insert(&tier, &tier_state);
+
+        let receiver_id = receiver_id.unwrap_or_else(env::predecessor_account_id);
+        let attributes = vec![(TIER_TRAIT.to_string(), tier)];
+        self.internal_buy(receiver_id, None, attributes, tier_state.price, (None, None), (None, None, None))
+    }
+
+    /// Gives `tier` its own metadata template, e.g. distinct artwork for VIP tickets. Its tickets
+    /// are minted from it instead of the contract's `token_metadata`, with the usual attributes
+    /// in `extra`. Allowed only before the tier's first sale.
+    #[payable]
+    pub fn set_tier_metadata(&mut self, tier: String, metadata: TokenMetadata) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        let mut tier_state = self.tiers.get(&tier).expect(ERR_TIER_NOT_FOUND);
+        assert_eq!(tier_state.minted, 0, "{}", ERR_TIER_ALREADY_MINTED);
+        tier_state.metadata = Some(metadata);
+        self.tiers.insert(&tier, &tier_state);
+    }
+
+    /// Returns the tier's own metadata template, or `None` if it uses the contract's.
+    pub fn tier_metadata(&self, tier: String) -> Option<TokenMetadata> {
+        self.tiers.get(&tier).expect(ERR_TIER_NOT_FOUND).metadata
+    }
+
+    /// Returns price and supply information for every tier.
+    pub fn all_tiers(&self) -> Vec<TierView> {
+        self.tiers.iter().map(|(name, tier)| tier.view(name)).collect()
+    }
+
+    pub fn tier_info(&self, tier: String) -> TierView {
+        self.tiers.get(&tier).expect(ERR_TIER_NOT_FOUND).view(tier)
+    }
+
+    /// Returns the tier the ticket was bought from, or `None` if it wasn't bought through `nft_buy_tier`.
+    pub fn tier_of(&self, token_id: TokenId) -> Option<String> {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+        get_attribute(&extra_to_attributes(&token.metadata.unwrap().extra), TIER_TRAIT).map(str::to_string)
+    }
+
+    /// Returns how many tickets of `tier` are currently redeemed.
+    pub fn redeemed_count_by_tier(&self, tier: String) -> u64 {
+        self.tiers.get(&tier).expect(ERR_TIER_NOT_FOUND).redeemed
+    }
+
+    #[payable]
+    pub fn redeem_nft(
+        &mut self,
+        token_id: TokenId
+    ) -> Token {
+        assert_one_yocto();
+        self.assert_redemptions_unlocked();
+        let caller_id = env::predecessor_account_id();
+
+        let token = self
+            .check_redeemable(&token_id, &caller_id)
+            .unwrap_or_else(|err| panic!("{}", err));
+        let token = self.internal_redeem(token);
+
+        emit_ticket_event("nft_redeem", json!({"owner_id": caller_id, "token_ids": [token_id]}));
+        token
+    }
+
+    /// Redeems the caller's ticket like `redeem_nft` and returns a record of the redemption, also
+    /// logged as an `nft_redemption_proof` event, for off-chain systems to verify.
+    #[payable]
+    pub fn redeem_with_proof(&mut self, token_id: TokenId) -> RedemptionProof {
+        let token = self.redeem_nft(token_id);
+        let proof = RedemptionProof {
+            contract_id: env::current_account_id(),
+            token_id: token.token_id,
+            redeemer_id: token.owner_id,
+            redeemed_at_ns: env::block_timestamp(),
+        };
+        emit_ticket_event("nft_redemption_proof", json!(proof));
+        proof
+    }
+
+    /// Redeems several of the caller's tickets at once, e.g. for a group checking in together.
+    /// With `stop_on_error` the whole call fails on the first ticket that can't be redeemed,
+    /// otherwise such tickets are skipped and left out of the result.
+    #[payable]
+    pub fn redeem_many(&mut self, token_ids: Vec<TokenId>, stop_on_error: bool) -> Vec<Token> {
+        assert_one_yocto();
+        self.assert_redemptions_unlocked();
+        assert!(token_ids.len() <= MAX_REDEEM_BATCH, "{}", fill(ERR_REDEEM_BATCH_TOO_LARGE, &[&MAX_REDEEM_BATCH]));
+        let caller_id = env::predecessor_account_id();
+
+        let mut redeemed = vec![];
+        for token_id in token_ids {
+            match self.check_redeemable(&token_id, &caller_id) {
+                Ok(token) => redeemed.push(self.internal_redeem(token)),
+                Err(err) if stop_on_error => panic!("{}", err),
+                Err(err) => env::log_str(&format!("Skipping token {}: {}", token_id, err)),
+            }
+        }
+
+        if !redeemed.is_empty() {
+            let redeemed_ids: Vec<&TokenId> = redeemed.iter().map(|token| &token.token_id).collect();
+            emit_ticket_event("nft_redeem", json!({"owner_id": caller_id, "token_ids": redeemed_ids}));
+        }
+        redeemed
+    }
+
+    /// Reverts a mistaken redemption, giving a multi-entry ticket back its last used entry. Only
+    /// the owner or an admin can call this, since holders un-redeeming their own tickets would let
+    /// them enter twice.
+    #[payable]
+    pub fn unredeem_nft(&mut self, token_id: TokenId) -> Token {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+
+        let mut token = self.tokens.nft_token(token_id.clone()).expect(ERR_TOKEN_NOT_FOUND);
+        let token_metadata = token.metadata.as_mut().unwrap();
+        let mut attributes = extra_to_attributes(&token_metadata.extra);
+        let (used, max) = redemption_counts(&attributes);
+        assert!(used > 0, "{}", ERR_NOT_REDEEMED);
+        if max > 1 {
+            set_attribute(&mut attributes, REDEMPTIONS_USED_TRAIT, &(used - 1).to_string());
+        }
+        if used == max {
+            set_attribute(&mut attributes, REDEEMED_TRAIT, "false");
+            self.redeemed_index.insert(&token_id, &false);
+            self.redeemed_count -= 1;
+            self.count_tier_redemption(&attributes, false);
+        }
+        token_metadata.extra = Some(attributes_to_extra(&attributes));
+
+        self.tokens.token_metadata_by_id.as_mut().unwrap().insert(&token_id, token_metadata);
+
+        emit_ticket_event("nft_unredeem", json!({"owner_id": token.owner_id, "token_ids": [token_id]}));
+        token
+    }
+
+    /// Sets how many times tickets minted from now on can be redeemed, e.g. for festival
+    /// wristbands allowing re-entry. Tickets already minted keep their own limit.
+    #[payable]
+    pub fn set_max_redemptions(&mut self, max_redemptions: u32) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        assert!(max_redemptions > 0, "{}", ERR_ZERO_MAX_REDEMPTIONS);
+        self.max_redemptions = max_redemptions;
+    }
+
+    pub fn max_redemptions(&self) -> u32 {
+        self.max_redemptions
+    }
+
+    /// Returns how many more times the ticket can be redeemed.
+    pub fn redemptions_remaining(&self, token_id: TokenId) -> u32 {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+        let (used, max) = redemption_counts(&extra_to_attributes(&token.metadata.unwrap().extra));
+        max - used
+    }
+
+    /// Returns how many tickets are currently redeemed.
+    pub fn redeemed_count(&self) -> u64 {
+        self.redeemed_count
+    }
+
+    /// Returns how many tokens have been burned, by their holders or through refunds. Tickets and
+    /// attendance tokens minted, less this, is `nft_total_supply`.
+    pub fn total_burned(&self) -> u64 {
+        self.burned_tokens + self.poaps_burned
+    }
+
+    /// Destroys a ticket on behalf of its owner and refunds the released storage to them.
+    /// Burned tickets still count towards the supply, so they can't be sold again.
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        assert_eq!(env::predecessor_account_id(), owner_id, "{}", ERR_NOT_TOKEN_OWNER);
+        self.assert_not_refunding(&token_id);
+        self.assert_not_consigned(&token_id);
+
+        let storage_refund = self.internal_burn(&token_id, &owner_id);
+        if storage_refund > 0 {
+            Promise::new(owner_id).transfer(storage_refund);
+        }
+    }
+
+    /// Returns what `refund_ticket` would pay out for the ticket right now under the refund
+    /// policy, or 0 if refunds are off, the ticket was used for entry, even once of several
+    /// allowed, or it wasn't bought with NEAR.
+    pub fn refund_quote(&self, token_id: TokenId) -> U128 {
+        let token = self.tokens.nft_token(token_id.clone()).expect(ERR_TOKEN_NOT_FOUND);
+        let policy = match &self.refund_policy {
+            Some(policy) => policy,
+            None => return U128(0),
+        };
+        let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+        if redemption_counts(&attributes).0 > 0 {
+            return U128(0);
+        }
+
+        let price = self.paid_prices.get(&token_id).unwrap_or(0);
+        let event_date_ns = self.event_info.event_date_ns;
+        let now = env::block_timestamp();
+        if now < event_date_ns.saturating_sub(policy.full_refund_window_ns) {
+            U128(price)
+        } else if now < event_date_ns {
+            royalty_to_payout(policy.partial_refund_bps, price)
+        } else {
+            U128(0)
+        }
+    }
+
+    /// Returns the ticket for the `refund_quote` amount out of the proceeds. The ticket is held
+    /// until the payment goes through: it can't be transferred, burned, redeemed or refunded
+    /// again in the meantime. `resolve_refund` then burns it and refunds its storage too; like
+    /// any burned ticket it isn't sold again. If the payment fails the ticket is released.
+    #[payable]
+    pub fn refund_ticket(&mut self, token_id: TokenId) -> U128 {
+        assert_one_yocto();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        assert_eq!(env::predecessor_account_id(), owner_id, "{}", ERR_NOT_TOKEN_OWNER);
+        self.assert_not_refunding(&token_id);
+        self.assert_not_consigned(&token_id);
+        let refund = self.refund_quote(token_id.clone()).0;
+        assert!(refund > 0, "{}", ERR_NOT_REFUNDABLE);
+        assert!(refund <= self.proceeds, "{}", ERR_REFUND_ABOVE_PROCEEDS);
+        self.proceeds -= refund;
+        self.refunds_in_progress.insert(&token_id);
+
+        Promise::new(owner_id.clone()).transfer(refund).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_REFUND)
+                .resolve_refund(token_id, owner_id, U128(refund)),
+        );
+        U128(refund)
+    }
+
+    /// Settles a `refund_ticket` payment: burns the ticket once it's paid and offers its seat to
+    /// the waitlist, or puts the refund back into the proceeds and releases the ticket if the
+    /// payment failed. Returns whether the refund went through.
+    #[private]
+    pub fn resolve_refund(&mut self, token_id: TokenId, owner_id: AccountId, refund: U128) -> bool {
+        self.refunds_in_progress.remove(&token_id);
+        let paid = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if !paid {
+            self.proceeds += refund.0;
+            return false;
+        }
+
+        self.refunds_issued += refund.0;
+        self.total_revenue = self.total_revenue.saturating_sub(refund.0);
+        let storage_refund = self.internal_burn(&token_id, &owner_id);
+        if storage_refund > 0 {
+            Promise::new(owner_id.clone()).transfer(storage_refund);
+        }
+        emit_ticket_event(
+            "nft_refund",
+            json!({"owner_id": owner_id, "token_ids": [token_id], "amount": refund}),
+        );
+        self.offer_freed_seat(&token_id);
+        true
+    }
+
+    /// Sets the refund schedule, or turns refunds off with `None`.
+    #[payable]
+    pub fn set_refund_policy(&mut self, refund_policy: Option<RefundPolicy>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(refund_policy) = &refund_policy {
+            assert!(refund_policy.partial_refund_bps <= 10000, "{}", ERR_PARTIAL_REFUND_TOO_HIGH);
+        }
+        self.refund_policy = refund_policy;
+    }
+
+    pub fn refund_policy(&self) -> Option<RefundPolicy> {
+        self.refund_policy.clone()
+    }
+
+    /// Hands the caller's ticket to the owner's resale pool at `asking_price`. The ticket moves
+    /// to the owner account until `resell_consigned` sells it or `cancel_consignment` returns
+    /// it, and can't be transferred, burned, redeemed or refunded in the meantime. Like
+    /// `nft_transfer` this needs 1 yoctoNEAR plus the storage for the transfer history and the
+    /// consignment record.
+    #[payable]
+    pub fn consign(&mut self, token_id: TokenId, asking_price: U128) {
+        let storage_deposit = self.assert_transfer_deposit(false);
+        let holder_id = env::predecessor_account_id();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        assert_eq!(holder_id, owner_id, "{}", ERR_NOT_TOKEN_OWNER);
+        assert_ne!(holder_id, self.tokens.owner_id, "{}", ERR_OWNER_CANNOT_CONSIGN);
+        assert!(asking_price.0 > 0, "{}", ERR_ZERO_ASKING_PRICE);
+
+        let initial_storage_usage = env::storage_usage();
+        self.consignments.insert(&token_id, &(holder_id.clone(), asking_price.0));
+        let record_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(
+            storage_deposit >= record_cost,
+            "{}",
+            fill(ERR_CONSIGNMENT_DEPOSIT, &[&(record_cost - storage_deposit)])
+        );
+
+        let contract_owner_id = self.tokens.owner_id.clone();
+        let (_, approved_account_ids) = self.internal_transfer(
+            &holder_id,
+            &contract_owner_id,
+            &token_id,
+            None,
+            None,
+            Some(storage_deposit - record_cost),
+        );
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(holder_id.clone(), &approved_account_ids);
+        }
+        emit_ticket_event(
+            "nft_consign",
+            json!({"owner_id": holder_id, "token_ids": [token_id], "asking_price": asking_price}),
+        );
+    }
+
+    /// Sells a consigned ticket to `buyer` for its asking price, attached by the caller along
+    /// with 1 yoctoNEAR and the transfer history storage. The original holder is paid the price
+    /// less `consignment_commission_bps`, which goes to the proceeds, plus the consignment's storage.
+    #[payable]
+    pub fn resell_consigned(&mut self, token_id: TokenId, buyer: AccountId) {
+        let (holder_id, asking_price) = self.consignments.get(&token_id).expect(ERR_NOT_CONSIGNED);
+        assert!(
+            env::attached_deposit() > asking_price,
+            "{}",
+            fill(ERR_RESALE_DEPOSIT, &[&asking_price])
+        );
+        let storage_deposit = env::attached_deposit() - asking_price - 1;
+
+        let initial_storage_usage = env::storage_usage();
+        self.consignments.remove(&token_id);
+        let released_storage = env::storage_byte_cost() * Balance::from(initial_storage_usage - env::storage_usage());
+        let commission = royalty_to_payout(self.consignment_commission_bps, asking_price).0;
+        self.proceeds += commission;
+
+        let contract_owner_id = self.tokens.owner_id.clone();
+        let (_, approved_account_ids) =
+            self.internal_transfer(&contract_owner_id, &buyer, &token_id, None, None, Some(storage_deposit));
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(contract_owner_id, &approved_account_ids);
+        }
+        Promise::new(holder_id.clone()).transfer(asking_price - commission + released_storage);
+        emit_ticket_event(
+            "nft_resell",
+            json!({
+                "owner_id": buyer,
+                "previous_owner_id": holder_id,
+                "token_ids": [token_id],
+                "price": U128(asking_price),
+            }),
+        );
+    }
+
+    /// Takes a ticket out of the resale pool and back to its original holder. Either the holder
+    /// or the owner can cancel. Like `nft_transfer` this needs 1 yoctoNEAR plus the transfer
+    /// history storage; the consignment's storage is refunded to the holder.
+    #[payable]
+    pub fn cancel_consignment(&mut self, token_id: TokenId) {
+        let storage_deposit = self.assert_transfer_deposit(false);
+        let (holder_id, _) = self.consignments.get(&token_id).expect(ERR_NOT_CONSIGNED);
+        let caller_id = env::predecessor_account_id();
+        assert!(caller_id == holder_id || caller_id == self.tokens.owner_id, "{}", ERR_NOT_CONSIGNOR);
+
+        let initial_storage_usage = env::storage_usage();
+        self.consignments.remove(&token_id);
+        let released_storage = env::storage_byte_cost() * Balance::from(initial_storage_usage - env::storage_usage());
+
+        let contract_owner_id = self.tokens.owner_id.clone();
+        let (_, approved_account_ids) =
+            self.internal_transfer(&contract_owner_id, &holder_id, &token_id, None, None, Some(storage_deposit));
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(contract_owner_id, &approved_account_ids);
+        }
+        Promise::new(holder_id.clone()).transfer(released_storage);
+        emit_ticket_event("nft_consign_cancel", json!({"owner_id": holder_id, "token_ids": [token_id]}));
+    }
+
+    /// Returns a page of the consigned tickets with their original holders and asking prices.
+    pub fn consigned_tickets(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<(TokenId, AccountId, U128)> {
+        self.consignments
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(clamp_limit(limit) as usize)
+            .map(|(token_id, (holder_id, asking_price))| (token_id, holder_id, U128(asking_price)))
+            .collect()
+    }
+
+    /// Sets the share of each consigned resale the contract keeps, in basis points.
+    #[payable]
+    pub fn set_consignment_commission_bps(&mut self, consignment_commission_bps: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(consignment_commission_bps <= 10000, "{}", ERR_COMMISSION_TOO_HIGH);
+        self.consignment_commission_bps = consignment_commission_bps;
+    }
+
+    pub fn consignment_commission_bps(&self) -> u32 {
+        self.consignment_commission_bps
+    }
+
+    /// Caps how many tickets a single account can accumulate through transfers, or lifts the cap with `None`.
+    #[payable]
+    pub fn set_max_held_per_account(&mut self, max_held_per_account: Option<u64>) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.max_held_per_account = max_held_per_account;
+    }
+
+    /// Returns whether `account_id` can be sent another ticket without exceeding `max_held_per_account`.
+    pub fn can_receive(&self, account_id: AccountId) -> bool {
+        self.max_held_per_account
+            .is_none_or(|max_held| self.tokens_held(&account_id) < max_held)
+    }
+
+    /// Adds `additional` tickets to the supply, e.g. after moving to a bigger venue. Supply can
+    /// only grow, so it never drops below the tickets already minted. The template's `copies`,
+    /// the edition size shown on each ticket, stays as it is.
+    #[payable]
+    pub fn increase_supply(&mut self, additional: u64) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(additional > 0, "{}", ERR_SUPPLY_NOT_INCREASED);
+
+        let old_supply = self.max_supply;
+        let new_supply = old_supply.checked_add(additional).expect(ERR_SUPPLY_OVERFLOW);
+        assert!(new_supply <= self.hard_max_supply, "{}", ERR_EXCEEDS_HARD_SUPPLY);
+        assert!(new_supply >= self.minted_tokens, "{}", ERR_SUPPLY_BELOW_MINTED);
+        self.max_supply = new_supply;
+        self.assert_supply_invariant();
+        env::log_str(&format!("Supply increased from {} to {}", old_supply, new_supply));
+        self.serve_waitlist();
+    }
+
+    /// Checks the supply caps still nest: minted tickets within `max_supply`, `max_supply` and the
+    /// tier supplies combined within `hard_max_supply`, and each tier's sales within its own
+    /// supply. Every mint and supply change asserts this, so it's only `false` if state was corrupted.
+    pub fn supply_invariant_ok(&self) -> bool {
+        let tiers_supply: u64 = self.tiers.values().map(|tier| tier.max_supply).sum();
+        self.minted_tokens <= self.max_supply
+            && self.max_supply <= self.hard_max_supply
+            && tiers_supply <= self.hard_max_supply
+            && self.tiers.values().all(|tier| tier.minted <= tier.max_supply)
+    }
+
+    /// Recomputes the cached `minted_tokens`, `burned_tokens` and `redeemed_count` from the
+    /// tokens themselves, for repairing them after a faulty migration, and logs a
+    /// `counters_reconciled` event for each counter it corrects. Attendance tokens are left out:
+    /// they have their own `poaps_minted` and `poaps_burned` counters. Burns leave no trace, so
+    /// the minted count is the highest live sequential ticket id or the live tokens plus the
+    /// cached burns, whichever is larger, and the burns are whatever that leaves unaccounted for.
+    /// Ids that can't be sequential tickets, like custom `nft_mint` ids or numbers beyond
+    /// `max_supply`, count as live tokens but never raise the highest id. Reads every token, so
+    /// only call it on a quiet contract with plenty of gas attached.
+    #[payable]
+    pub fn reconcile_minted(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+
+        let token_metadata_by_id = self.tokens.token_metadata_by_id.as_ref().unwrap();
+        let (mut live_tokens, mut highest_ticket, mut redeemed) = (0u64, 0u64, 0u64);
+        for (token_id, _) in self.tokens.owner_by_id.iter() {
+            if token_id.starts_with(POAP_TOKEN_PREFIX) {
+                continue;
+            }
+            live_tokens += 1;
+            if let Ok(id) = token_id.parse::<u64>() {
+                if (1..=self.max_supply).contains(&id) && self.format_token_id(id) == token_id {
+                    highest_ticket = highest_ticket.max(id);
+                }
+            }
+            let redeemed_flag = token_metadata_by_id
+                .get(&token_id)
+                .and_then(|token_metadata| {
+                    get_attribute(&extra_to_attributes(&token_metadata.extra), REDEEMED_TRAIT).map(str::to_string)
+                });
+            if redeemed_flag.as_deref() == Some("true") {
+                redeemed += 1;
+            }
+        }
+        let minted = highest_ticket.max(live_tokens + self.burned_tokens);
+
+        for (counter, cached, actual) in [
+            ("minted_tokens", &mut self.minted_tokens, minted),
+            ("burned_tokens", &mut self.burned_tokens, minted - live_tokens),
+            ("redeemed_count", &mut self.redeemed_count, redeemed),
+        ] {
+            if *cached != actual {
+                emit_ticket_event("counters_reconciled", json!({"counter": counter, "from": *cached, "to": actual}));
+                *cached = actual;
+            }
+        }
+        self.assert_supply_invariant();
+    }
+
+    /// Joins the waitlist of a sold-out sale. The deposit, at least the minting price plus
+    /// `MIN_STORAGE_BALANCE_BYTES` of storage, is held until a ticket frees up, which is then
+    /// minted to the caller with the unused deposit refunded.
+    #[payable]
+    pub fn join_waitlist(&mut self) {
+        let account_id = env::predecessor_account_id();
+        self.assert_not_blocked(&account_id);
+        assert_eq!(self.tokens_left(), 0, "{}", ERR_STILL_ON_SALE);
+        assert!(self.waitlist_position(account_id.clone()).is_none(), "{}", ERR_ALREADY_ON_WAITLIST);
+        assert!(self.waitlist.len() < MAX_WAITLIST_LEN, "{}", ERR_WAITLIST_FULL);
+        let required_deposit =
+            self.minting_price + env::storage_byte_cost() * Balance::from(MIN_STORAGE_BALANCE_BYTES);
+        assert!(
+            env::attached_deposit() >= required_deposit,
+            "{}",
+            fill(ERR_WAITLIST_DEPOSIT, &[&required_deposit])
+        );
+        self.waitlist.push(&(account_id, env::attached_deposit()));
+    }
+
+    /// Leaves the waitlist and refunds the held deposit.
+    #[payable]
+    pub fn leave_waitlist(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let position = self.waitlist_position(account_id.clone()).expect(ERR_NOT_ON_WAITLIST);
+        let (_, deposit) = self.remove_waitlist_entry(position - 1);
+        Promise::new(account_id).transfer(deposit);
+    }
+
+    /// Returns the account's place on the waitlist, 1 being served next, or `None` if it's not on it.
+    pub fn waitlist_position(&self, account_id: AccountId) -> Option<u64> {
+        self.waitlist
+            .iter()
+            .position(|(waiting_id, _)| waiting_id == account_id)
+            .map(|index| index as u64 + 1)
+    }
+
+    /// Lets `account_id` run operational methods (comp mints, airdrops, promo codes, tiers,
+    /// redemption and transfer rules, event info) alongside the owner. Raw `nft_mint`, royalties,
+    /// fees, payment settings and supply stay with the owner, who also keeps receiving the sale
+    /// residual.
+    #[payable]
+    pub fn add_admin(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.admins.insert(&account_id);
+    }
+
+    /// Revokes an admin. Any admin or the owner can do this, so a compromised admin can be removed
+    /// by the others.
+    #[payable]
+    pub fn remove_admin(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(self.admins.remove(&account_id), "{}", ERR_NOT_ADMIN);
+    }
+
+    pub fn is_admin(&self, account_id: AccountId) -> bool {
+        self.admins.contains(&account_id)
+    }
+
+    /// Returns the admins, not including the owner.
+    pub fn list_admins(&self) -> Vec<AccountId> {
+        self.admins.to_vec()
+    }
+
+    /// Blocks `account_id` from buying, receiving and sending tickets, e.g. for sanctions
+    /// compliance. Blocked accounts can't buy (`nft_buy`, `nft_buy_signed`, `nft_buy_tier`,
+    /// `ft_on_transfer`) or join the waitlist, no ticket can be minted to them, and transfers
+    /// to or from them are rejected. Blocked waitlist entries are refunded instead of served.
+    #[payable]
+    pub fn block_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.blocked_accounts.insert(&account_id);
+    }
+
+    #[payable]
+    pub fn unblock_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(self.blocked_accounts.remove(&account_id), "{}", ERR_NOT_BLOCKED);
+    }
+
+    pub fn is_blocked(&self, account_id: AccountId) -> bool {
+        self.blocked_accounts.contains(&account_id)
+    }
+
+    /// Returns the crate version this contract was built from, followed by its optional
+    /// capabilities, e.g. "1.1.0 (tiers,ft_payment)".
+    pub fn contract_version(&self) -> String {
+        format!("{} ({})", env!("CARGO_PKG_VERSION"), CONTRACT_FEATURES.join(","))
+    }
+
+    /// Returns minted, remaining and price in one call, along with the redemption kill switch.
+    /// The contract has no sale pause, so `paused` is always `false`, and `sale_status` is
+    /// "on_sale" or the reason `purchasability` gives.
+    pub fn minting_progress(&self) -> MintingProgress {
+        let remaining = self.tokens_left();
+        let sale_status = match self.purchasability() {
+            (true, _) => "on_sale".to_string(),
+            (false, reason) => reason.unwrap(),
+        };
+        MintingProgress {
+            minted: self.minted_tokens,
+            max_supply: self.max_supply,
+            remaining,
+            price: U128(self.minting_price),
+            paused: false,
+            redemptions_locked: self.redemptions_locked,
+            sale_status,
+        }
+    }
+
+    /// Returns the post-event report numbers in one call. `gross_revenue` is the NEAR collected
+    /// from sales before refunds, `refunds_issued` counts the ticket refunds that were paid out, and `current_balance` is
+    /// the `proceeds` not yet withdrawn.
+    pub fn sales_summary(&self) -> SalesSummary {
+        SalesSummary {
+            total_minted: self.minted_tokens,
+            total_redeemed: self.redeemed_count,
+            total_burned: self.burned_tokens,
+            gross_revenue: U128(self.total_revenue + self.refunds_issued),
+            refunds_issued: U128(self.refunds_issued),
+            current_balance: U128(self.proceeds),
+        }
+    }
+
+    /// Returns `(true, None)` while `nft_buy` would mint a ticket, or `(false, Some(reason))` with
+    /// the check it would fail: "minting_closed" or "sold_out". The contract has neither a sale
+    /// pause ("paused") nor a sale window ("sale_not_started", "sale_ended").
+    pub fn purchasability(&self) -> (bool, Option<String>) {
+        if self.is_minting_closed() {
+            return (false, Some("minting_closed".to_string()));
+        }
+        if self.tokens_left() == 0 {
+            return (false, Some("sold_out".to_string()));
+        }
+        (true, None)
+    }
+
+    /// Returns how many tickets can be minted, which `increase_supply` can raise up to
+    /// `hard_max_supply`. This is separate from the template's `copies`, the edition size
+    /// displayed on each ticket, and starts out equal to it.
+    pub fn max_supply(&self) -> u64 {
+        self.max_supply
+    }
+
+    /// Returns the most tickets that can ever exist, whatever `increase_supply` or the tiers allow.
+    pub fn hard_max_supply(&self) -> u64 {
+        self.hard_max_supply
+    }
+
+    pub fn tokens_left(&self) -> u64 {
+        self.max_supply - self.minted_tokens
+    }
+
+    /// Returns `(true, None)` if the ticket can be redeemed right now, or `(false, Some(reason))`
+    /// with one of "redemptions_locked", "refund_in_progress", "consigned", "not_found",
+    /// "already_redeemed", "not_yet_valid" or "expired".
+    pub fn nft_is_redeemable(&self, token_id: TokenId) -> (bool, Option<String>) {
+        if self.redemptions_locked {
+            return (false, Some(RedeemError::Locked.reason().to_string()));
+        }
+        if self.refunds_in_progress.contains(&token_id) {
+            return (false, Some(RedeemError::RefundInProgress.reason().to_string()));
+        }
+        if self.is_consigned(&token_id) {
+            return (false, Some(RedeemError::Consigned.reason().to_string()));
+        }
+        let validity = self
+            .tokens
+            .nft_token(token_id)
+            .ok_or(RedeemError::NotFound)
+            .and_then(|token| check_ticket_validity(token.metadata.as_ref().unwrap(), self.redemption_grace_ns));
+        match validity {
+            Ok(()) => (true, None),
+            Err(err) => (false, Some(err.reason().to_string())),
+        }
+    }
+
+    /// Returns the ticket's `(starts_at, expires_at)` in nanoseconds: the window set when it was
+    /// bought, or else the template's. Redemption also allows `redemption_grace_ns` past the end.
+    pub fn validity_window(&self, token_id: TokenId) -> (Option<u64>, Option<u64>) {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+        let token_metadata = token.metadata.unwrap();
+        (parse_timestamp(&token_metadata.starts_at), parse_timestamp(&token_metadata.expires_at))
+    }
+
+    /// Returns whether the ticket's `expires_at` plus the grace period has passed, after which
+    /// it can't be redeemed.
+    pub fn is_expired(&self, token_id: TokenId) -> bool {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+        is_past_expiry(token.metadata.as_ref().unwrap(), self.redemption_grace_ns)
+    }
+
+    /// Lets tickets be redeemed for `redemption_grace_ns` nanoseconds past their `expires_at`,
+    /// e.g. for late entry.
+    #[payable]
+    pub fn set_redemption_grace_ns(&mut self, redemption_grace_ns: u64) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.redemption_grace_ns = redemption_grace_ns;
+    }
+
+    pub fn redemption_grace_ns(&self) -> u64 {
+        self.redemption_grace_ns
+    }
+
+    /// Blocks transfers of a ticket until `transfer_cooldown_ns` nanoseconds after it was minted,
+    /// to slow down bots flipping fresh tickets, or lifts the cooldown with `None`. Transfers by
+    /// the contract owner are exempt so support can still move tickets.
+    #[payable]
+    pub fn set_transfer_cooldown_ns(&mut self, transfer_cooldown_ns: Option<u64>) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.transfer_cooldown_ns = transfer_cooldown_ns;
+    }
+
+    /// Returns the block timestamp from which the ticket can be transferred, or 0 if it has no cooldown.
+    pub fn transferable_at(&self, token_id: TokenId) -> u64 {
+        match (self.transfer_cooldown_ns, self.minted_at.get(&token_id)) {
+            (Some(cooldown), Some(minted_at)) => minted_at.saturating_add(cooldown),
+            _ => 0,
+        }
+    }
+
+    /// Makes redeemed tickets non-transferable, keeping them with the attendee as a memento, or
+    /// lets them trade freely as collectibles again. Multi-entry tickets lock once all their
+    /// entries are used.
+    #[payable]
+    pub fn set_lock_transfer_after_redeem(&mut self, lock_transfer_after_redeem: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.lock_transfer_after_redeem = lock_transfer_after_redeem;
+    }
+
+    pub fn lock_transfer_after_redeem(&self) -> bool {
+        self.lock_transfer_after_redeem
+    }
+
+    /// Accepts `price` of the NEP-141 token `token_id` for tickets bought through `ft_transfer_call`,
+    /// or stops accepting token payments with `None`.
+    #[payable]
+    pub fn set_payment_token(&mut self, token_id: Option<AccountId>, price: U128) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.payment_token = token_id.map(|token_id| (token_id, price.0));
+    }
+
+    /// Returns the accepted payment token and its ticket price, if token payments are enabled.
+    pub fn payment_token(&self) -> Option<(AccountId, U128)> {
+        self.payment_token.clone().map(|(token_id, price)| (token_id, U128(price)))
+    }
+
+    /// Pages through all tokens like `nft_tokens`, with the redeemed flag, seat and tier parsed
+    /// from each ticket's attributes.
+    pub fn nft_tokens_detailed(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<TokenDetailed> {
+        self.nft_tokens(from_index, Some(clamp_limit(limit)))
+            .into_iter()
+            .map(|token| {
+                let attributes = extra_to_attributes(&token.metadata.as_ref().unwrap().extra);
+                TokenDetailed {
+                    redeemed: get_attribute(&attributes, REDEEMED_TRAIT) == Some("true"),
+                    seat: get_attribute(&attributes, SEAT_TRAIT).map(str::to_string),
+                    tier: get_attribute(&attributes, TIER_TRAIT).map(str::to_string),
+                    token,
+                }
+            })
+            .collect()
+    }
+
+    /// Looks up each of `token_ids` in order, with `None` for ids that don't exist (yet or
+    /// anymore), so a batch of possibly burned tickets never fails as a whole. Takes at most
+    /// `MAX_LIMIT` ids.
+    pub fn nft_tokens_by_ids(&self, token_ids: Vec<TokenId>) -> Vec<Option<Token>> {
+        assert!(
+            token_ids.len() as u64 <= MAX_LIMIT,
+            "{}",
+            fill(ERR_TOO_MANY_TOKEN_IDS, &[&MAX_LIMIT])
+        );
+        token_ids.into_iter().map(|token_id| self.tokens.nft_token(token_id)).collect()
+    }
+
+    /// Pages through the owner's tokens in ascending token id order, unlike
+    /// `nft_tokens_for_owner`, whose order shifts as tokens come and go. Numeric ids, as assigned
+    /// by `nft_buy`, sort numerically and come before any other ids, which sort as strings.
+    pub fn nft_tokens_for_owner_sorted(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let mut token_ids = match self.tokens.tokens_per_owner.as_ref().unwrap().get(&account_id) {
+            Some(token_ids) => token_ids.to_vec(),
+            None => return vec![],
+        };
+        token_ids.sort_by(|a, b| cmp_token_ids(a, b));
+        token_ids
+            .into_iter()
+            .skip(from_index.map_or(0, |from_index| from_index.0 as usize))
+            .take(clamp_limit(limit) as usize)
+            .map(|token_id| self.tokens.nft_token(token_id).unwrap())
+            .collect()
+    }
+
+    /// Returns how many tokens the account holds, like `nft_supply_for_owner` but as a plain
+    /// number for badges and frequent polling.
+    pub fn tokens_owned_count(&self, account_id: AccountId) -> u64 {
+        self.tokens_held(&account_id)
+    }
+
+    /// Returns whether the account holds at least one token, for apps gating perks on ticket
+    /// ownership. Tokens can change hands at any time, so re-check right before granting
+    /// time-sensitive access rather than caching the answer.
+    pub fn verify_ownership(&self, account_id: AccountId) -> bool {
+        self.tokens
+            .tokens_per_owner
+            .as_ref()
+            .unwrap()
+            .get(&account_id)
+            .is_some_and(|token_ids| !token_ids.is_empty())
+    }
+
+    /// Returns whether the account holds a ticket that has been redeemed at least once, for
+    /// perks reserved for attendees who checked in. Like `verify_ownership`, the answer can
+    /// change with every transfer.
+    pub fn owns_redeemed(&self, account_id: AccountId) -> bool {
+        let token_ids = match self.tokens.tokens_per_owner.as_ref().unwrap().get(&account_id) {
+            Some(token_ids) => token_ids,
+            None => return false,
+        };
+        let token_metadata_by_id = self.tokens.token_metadata_by_id.as_ref().unwrap();
+        let owns_redeemed = token_ids.iter().any(|token_id| {
+            token_metadata_by_id
+                .get(&token_id)
+                .is_some_and(|token_metadata| redemption_counts(&extra_to_attributes(&token_metadata.extra)).0 > 0)
+        });
+        owns_redeemed
+    }
+
+    /// Returns whether the ticket has used up all its redemptions. This reads the redeemed index
+    /// rather than parsing the metadata, so scanners can call it cheaply; the `redeemed`
+    /// attribute in `extra` stays the displayed source of truth and is kept in sync with it.
+    /// Tokens minted before the index existed fall back to the attribute.
+    pub fn is_redeemed(&self, token_id: TokenId) -> bool {
+        if let Some(redeemed) = self.redeemed_index.get(&token_id) {
+            return redeemed;
+        }
+        let token_metadata =
+            self.tokens.token_metadata_by_id.as_ref().unwrap().get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        get_attribute(&extra_to_attributes(&token_metadata.extra), REDEEMED_TRAIT) == Some("true")
+    }
+
+    /// Returns the memo of the latest transfer of the ticket that had one, e.g. the reason for a
+    /// support transfer. Only the latest memo is kept, truncated to `MAX_STORED_MEMO_LEN` characters.
+    pub fn last_transfer_memo(&self, token_id: TokenId) -> Option<String> {
+        self.transfer_memos.get(&token_id)
+    }
+
+    /// Returns a page of the ticket's transfers, oldest first.
+    pub fn transfer_history(&self, token_id: TokenId, from_index: Option<u64>, limit: Option<u64>) -> Vec<TransferRecord> {
+        match self.transfer_history.get(&token_id) {
+            Some(history) => history
+                .iter()
+                .skip(from_index.unwrap_or(0) as usize)
+                .take(clamp_limit(limit) as usize)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the ticket's attributes as `(trait_type, value)` pairs parsed from its `extra` JSON,
+    /// or an empty list if it has none.
+    pub fn token_attributes(&self, token_id: TokenId) -> Vec<(String, String)> {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+        extra_to_attributes(&token.metadata.unwrap().extra)
+    }
+
+    /// Updates the ticket template, e.g. once the event artwork is final. Only the fields passed
+    /// as `Some` change. Allowed only before the first mint so sold tickets never change.
+    #[payable]
+    pub fn set_token_template(
+        &mut self,
+        title: Option<String>,
+        description: Option<String>,
+        media: Option<String>,
+        media_hash: Option<Base64VecU8>,
+        reference: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        assert_eq!(self.minted_tokens, 0, "{}", ERR_ALREADY_MINTED);
+
+        if title.is_some() {
+            self.token_metadata.title = title;
+        }
+        if description.is_some() {
+            self.token_metadata.description = description;
+        }
+        if media.is_some() {
+            self.token_metadata.media = media;
+        }
+        if media_hash.is_some() {
+            self.token_metadata.media_hash = media_hash;
+        }
+        if reference.is_some() {
+            self.token_metadata.reference = reference;
+        }
+    }
+
+    /// Emergency switch that stops every redemption path, e.g. during a security incident,
+    /// while sales and transfers carry on.
+    #[payable]
+    pub fn set_redemptions_locked(&mut self, locked: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.redemptions_locked = locked;
+    }
+
+    pub fn redemptions_locked(&self) -> bool {
+        self.redemptions_locked
+    }
+
+    /// Adds a vetted marketplace to the accounts holders may approve. While the list is empty
+    /// any account can be approved.
+    #[payable]
+    pub fn allow_marketplace(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.allowed_marketplaces.insert(&account_id);
+    }
+
+    /// Removes a marketplace from the approvable accounts. Existing approvals stay until revoked.
+    #[payable]
+    pub fn disallow_marketplace(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(self.allowed_marketplaces.remove(&account_id), "{}", ERR_MARKETPLACE_NOT_LISTED);
+    }
+
+    pub fn allowed_marketplaces(&self) -> Vec<AccountId> {
+        self.allowed_marketplaces.to_vec()
+    }
+
+    /// Approves `account_id` for each of the caller's `token_ids` in one transaction, e.g. to
+    /// list many tickets on a marketplace, and returns the approval ids in the same order. The
+    /// attached deposit must cover the storage of all new approvals; the rest is refunded. Unlike
+    /// `nft_approve` no `nft_on_approve` call is made. At most `MAX_APPROVE_MANY` (50) tokens
+    /// per call.
+    #[payable]
+    pub fn nft_approve_many(&mut self, token_ids: Vec<TokenId>, account_id: AccountId) -> Vec<u64> {
+        assert!(env::attached_deposit() >= 1, "{}", ERR_ONE_YOCTO_REQUIRED);
+        assert!(token_ids.len() <= MAX_APPROVE_MANY, "{}", fill(ERR_TOO_MANY_APPROVE_MANY, &[&MAX_APPROVE_MANY]));
+        let mut storage_used = 0;
+        let approval_ids = token_ids
+            .iter()
+            .map(|token_id| {
+                let (_, approval_id, token_storage_used) = self.internal_approve(token_id, &account_id);
+                storage_used += token_storage_used;
+                approval_id
+            })
+            .collect();
+        refund_deposit(storage_used);
+        approval_ids
+    }
+
+    /// Sets how many accounts can be approved for a single token at once. Tokens already above
+    /// the new cap keep their approvals but can't add more.
+    #[payable]
+    pub fn set_max_approvals_per_token(&mut self, max_approvals_per_token: u32) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(max_approvals_per_token > 0, "{}", ERR_ZERO_APPROVALS);
+        self.max_approvals_per_token = max_approvals_per_token;
+    }
+
+    pub fn max_approvals_per_token(&self) -> u32 {
+        self.max_approvals_per_token
+    }
+
+    /// Sets the gas `nft_transfer_call` reserves for the receiver's `nft_on_transfer` when the
+    /// caller doesn't pick an amount.
+    #[payable]
+    pub fn set_default_call_gas(&mut self, default_call_gas: u64) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.default_call_gas = default_call_gas;
+    }
+
+    pub fn default_call_gas(&self) -> u64 {
+        self.default_call_gas
+    }
+
+    /// Sets the metadata of the attendance token (POAP) minted to each holder when their ticket
+    /// is redeemed, or `None` to stop minting them. Attendance tokens have the id `poap-<ticket
+    /// id>`, can't be transferred and don't count towards the ticket supply.
+    #[payable]
+    pub fn set_poap_metadata(&mut self, poap_metadata: Option<TokenMetadata>) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        self.poap_metadata = poap_metadata;
+    }
+
+    pub fn poap_metadata(&self) -> Option<TokenMetadata> {
+        self.poap_metadata.clone()
+    }
+
+    /// Returns how many attendance tokens have been minted. They're listed by the enumeration
+    /// views alongside the tickets.
+    pub fn poaps_minted(&self) -> u64 {
+        self.poaps_minted
+    }
+
+    /// Replaces the event details, e.g. once the venue is confirmed. Allowed only before the
+    /// first mint so buyers never see the event change under them.
+    #[payable]
+    pub fn set_event_info(&mut self, event_info: EventInfo) {
+        assert_one_yocto();
+        self.assert_admin_or_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        assert_eq!(self.minted_tokens, 0, "{}", ERR_ALREADY_MINTED);
+        self.event_info = event_info;
+    }
+
+    pub fn event_info(&self) -> EventInfo {
+        self.event_info.clone()
+    }
+
+    /// Turns on mystery-box tickets: every bought ticket gets a `rarity` attribute drawn from
+    /// `weights`, a list of traits with relative weights, e.g. `[["common", 90], ["rare", 10]]`.
+    /// `None` turns it off. Allowed only before the first mint so the odds can't change
+    /// mid-sale.
+    ///
+    /// The draw uses `env::random_seed`, which validators know in advance and can influence, so
+    /// it's only suitable for cosmetic traits, never for prizes worth gaming the draw for.
+    #[payable]
+    pub fn set_random_traits(&mut self, weights: Option<Vec<(String, u32)>>) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert_eq!(self.minted_tokens, 0, "{}", ERR_ALREADY_MINTED);
+        if let Some(weights) = &weights {
+            assert!(weights.iter().any(|(_, weight)| *weight > 0), "{}", ERR_ZERO_TRAIT_WEIGHTS);
+        }
+        self.random_traits = weights;
+    }
+
+    pub fn random_traits(&self) -> Option<Vec<(String, u32)>> {
+        self.random_traits.clone()
+    }
+
+    /// Returns `nft_metadata` together with the event, price and ticket counts, so a wallet can
+    /// render an event card from one call. `total_supply` counts the tickets in circulation, i.e.
+    /// minted less burned, not attendance tokens.
+    /// Tickets are never soulbound and transfers are never locked contract-wide; only the
+    /// per-ticket `transfer_cooldown_ns` applies, so those flags are `false` and `None`.
+    pub fn nft_ticket_metadata(&self) -> TicketContractMetadata {
+        TicketContractMetadata {
+            metadata: self.metadata.get().unwrap(),
+            event_info: self.event_info.clone(),
+            price: U128(self.minting_price),
+            total_supply: self.minted_tokens - self.burned_tokens,
+            redeemed_count: self.redeemed_count,
+            soulbound: false,
+            transfers_locked_until: None,
+        }
+    }
+
+    /// Switches minimal metadata mode, which saves storage on large collections. In this mode
+    /// new tickets keep only the template's `title` and `media`, the validity window the
+    /// redemption checks need, and the `extra` attributes with the redeemed flag. Wallets find
+    /// the description and everything else through the contract's `base_uri` and `reference`.
+    /// Tickets minted earlier keep their full metadata.
+    #[payable]
+    pub fn set_minimal_metadata(&mut self, minimal_metadata: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        self.minimal_metadata = minimal_metadata;
+    }
+
+    pub fn minimal_metadata(&self) -> bool {
+        self.minimal_metadata
+    }
+
+    /// Points the contract's `base_uri` at new media hosting, e.g. after moving CDNs. Per-token
+    /// `media` stays as it is, so relative paths resolve against the new host.
+    #[payable]
+    pub fn set_base_uri(&mut self, base_uri: String) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(!self.metadata_frozen, "{}", ERR_METADATA_FROZEN);
+        assert!(
+            base_uri.starts_with("https://") || base_uri.starts_with("http://") || base_uri.starts_with("ipfs://"),
+            "{}",
+            ERR_INVALID_BASE_URI
+        );
+        let mut metadata = self.metadata.get().unwrap();
+        metadata.base_uri = Some(base_uri);
+        self.metadata.set(&metadata);
+    }
+
+    /// Permanently locks the ticket template, event details and base URI, so buyers know the artwork
+    /// and event can't change after the sale. This can't be undone.
+    #[payable]
+    pub fn freeze_metadata(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.metadata_frozen = true;
+    }
+
+    pub fn metadata_frozen(&self) -> bool {
+        self.metadata_frozen
+    }
+
+    /// Returns the account that owns the contract and can call its owner-only methods.
+    pub fn owner_id(&self) -> AccountId {
+        self.tokens.owner_id.clone()
+    }
+
+    /// Returns the owners of the sequential ticket ids `from_token_id..=to_token_id`, as assigned
+    /// by `nft_buy`, skipping ids that don't exist (yet or anymore). Spans at most `MAX_LIMIT` ids.
+    pub fn owners_in_range(&self, from_token_id: u64, to_token_id: u64) -> Vec<(TokenId, AccountId)> {
+        assert!(from_token_id <= to_token_id, "{}", ERR_INVALID_TOKEN_RANGE);
+        assert!(
+            to_token_id - from_token_id < MAX_LIMIT,
+            "{}",
+            fill(ERR_TOKEN_RANGE_TOO_WIDE, &[&MAX_LIMIT])
+        );
+        (from_token_id..=to_token_id)
+            .filter_map(|id| {
+                let token_id = self.format_token_id(id);
+                self.tokens.owner_by_id.get(&token_id).map(|owner_id| (token_id, owner_id))
+            })
+            .collect()
+    }
+
+    /// Returns the metadata template every ticket bought through `nft_buy` is minted from.
+    pub fn ticket_template(&self) -> TokenMetadata {
+        self.token_metadata.clone()
+    }
+
+    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: u32) -> Payout {
+		let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+
+		self.internal_payout(&token.owner_id, balance, max_len_payout)
+	}
+
+    /// `nft_payout` for marketplaces that don't pass `max_len_payout`: every royalty is paid, so
+    /// the payout has one entry per royalty recipient plus the owner.
+    pub fn nft_payout_default(&self, token_id: TokenId, balance: U128) -> Payout {
+        self.nft_payout(token_id, balance, u32::MAX)
+    }
+
+    /// Returns the basis-point split `nft_payout` uses for `token_id`, including the owner's residual.
+    /// A royalty recipient that currently owns the token gets no royalty on top of the residual.
+    pub fn effective_royalties(&self, token_id: TokenId) -> HashMap<AccountId, u32> {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+
+        self.royalty_split(&token.owner_id, usize::MAX)
+    }
+
+    /// Returns how a sale at `sale_price` would be split between the royalty recipients and the
+    /// owner, with every royalty included. Uses the same math as `nft_transfer_payout`, so the
+    /// settlement matches the preview whenever the marketplace's `max_len_payout` fits all royalties.
+    pub fn payout_preview(&self, token_id: TokenId, sale_price: U128) -> Payout {
+        let token = self.tokens.nft_token(token_id).expect(ERR_TOKEN_NOT_FOUND);
+
+        self.internal_payout(&token.owner_id, sale_price, u32::MAX)
+    }
+
+    //transfers the token to the receiver ID and returns the payout object that should be payed given the passed in balance. 
+    #[payable]
+    pub fn nft_transfer_payout(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        memo: Option<String>,
+        balance: U128,
+        max_len_payout: u32,
+    ) -> Payout { 
+        let storage_deposit = self.assert_transfer_deposit(true);
+        // NEP-199 marketplaces attach exactly 1 yoctoNEAR, so unless more is attached the
+        // contract pays for the sale's history and payout records.
+        let storage_deposit = Some(storage_deposit).filter(|storage_deposit| *storage_deposit > 0);
+        let sender_id = env::predecessor_account_id();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        let payout = self.internal_payout(&owner_id, balance, max_len_payout);
+        let storage_deposit = if self.payout_confirmation_required {
+            let storage_cost = self.record_pending_payout(&token_id, &sender_id, &payout, storage_deposit);
+            storage_deposit.map(|storage_deposit| storage_deposit - storage_cost)
+        } else {
+            storage_deposit
+        };
+        let (owner_id, approved_account_ids) = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            Some(approval_id),
+            memo,
+            storage_deposit,
+        );
+
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(
+                owner_id,
+                &approved_account_ids,
+            );
+        }
+
+		payout
+    }
+
+    /// `nft_transfer_payout` for marketplaces that don't pass `max_len_payout`: every royalty is
+    /// paid, as with `nft_payout_default`.
+    #[payable]
+    pub fn nft_transfer_payout_default(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: u64,
+        memo: Option<String>,
+        balance: U128,
+    ) -> Payout {
+        self.nft_transfer_payout(receiver_id, token_id, approval_id, memo, balance, u32::MAX)
+    }
+
+    /// Called by the marketplace after settling an `nft_transfer_payout` sale, with what it
+    /// actually paid each account. Amounts that differ from the returned `Payout` flag the sale
+    /// in `flagged_payouts` and log an `nft_payout_mismatch` event. This can't make marketplaces
+    /// honor royalties, but it leaves an on-chain record of the ones that don't. Only expected
+    /// while `payout_confirmation_required` is on.
+    pub fn confirm_payout(&mut self, token_id: TokenId, paid: HashMap<AccountId, U128>) {
+        let marketplace_id = env::predecessor_account_id();
+        let (expected_marketplace_id, expected) = self.pending_payouts.get(&token_id).expect(ERR_NO_PENDING_PAYOUT);
+        assert_eq!(marketplace_id, expected_marketplace_id, "{}", ERR_NOT_PAYOUT_MARKETPLACE);
+        self.pending_payouts.remove(&token_id);
+
+        // Zero shares need no payment, so leaving them out of `paid` isn't a mismatch.
+        let paid: HashMap<AccountId, Balance> = paid
+            .into_iter()
+            .filter(|(_, amount)| amount.0 > 0)
+            .map(|(account_id, amount)| (account_id, amount.0))
+            .collect();
+        let expected_nonzero: HashMap<AccountId, Balance> =
+            expected.into_iter().filter(|(_, amount)| *amount > 0).collect();
+        if paid != expected_nonzero {
+            self.flagged_payouts.push(&FlaggedPayout {
+                token_id: token_id.clone(),
+                marketplace_id: marketplace_id.clone(),
+                flagged_at: env::block_timestamp(),
+            });
+            let to_json = |amounts: &HashMap<AccountId, Balance>| -> HashMap<AccountId, U128> {
+                amounts.iter().map(|(account_id, amount)| (account_id.clone(), U128(*amount))).collect()
+            };
+            emit_ticket_event(
+                "nft_payout_mismatch",
+                json!({
+                    "token_id": token_id,
+                    "marketplace_id": marketplace_id,
+                    "expected": to_json(&expected_nonzero),
+                    "paid": to_json(&paid),
+                }),
+            );
+        }
+    }
+
+    /// Makes `nft_transfer_payout` remember each payout it returns until the marketplace
+    /// reports the amounts it paid with `confirm_payout`. The record's storage is paid from the
+    /// transfer deposit.
+    #[payable]
+    pub fn set_payout_confirmation_required(&mut self, payout_confirmation_required: bool) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.payout_confirmation_required = payout_confirmation_required;
+    }
+
+    pub fn payout_confirmation_required(&self) -> bool {
+        self.payout_confirmation_required
+    }
+
+    /// Returns a page of the marketplace sales whose confirmed payout didn't match, oldest first.
+    pub fn flagged_payouts(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<FlaggedPayout> {
+        self.flagged_payouts
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(clamp_limit(limit) as usize)
+            .collect()
+    }
+
+    /// Transfers the caller's token and pays the royalties of an off-platform sale at
+    /// `sale_price` straight to their recipients. The deposit must cover those royalties on
+    /// top of the usual transfer deposit; the seller's share is settled between the parties.
+    #[payable]
+    pub fn nft_transfer_with_royalty(&mut self, receiver_id: AccountId, token_id: TokenId, sale_price: U128) {
+        let storage_deposit = self.assert_transfer_deposit(self.transfer_fee_on_plain_transfers);
+        let sender_id = env::predecessor_account_id();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        let mut royalties = self.internal_payout(&owner_id, sale_price, u32::MAX).payout;
+        royalties.remove(&owner_id);
+        let royalties_total: u128 = royalties.values().map(|amount| amount.0).sum();
+        assert!(
+            storage_deposit >= royalties_total,
+            "{}",
+            fill(ERR_ROYALTY_DEPOSIT, &[&royalties_total])
+        );
+
+        let (owner_id, approved_account_ids) = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            None,
+            None,
+            Some(storage_deposit - royalties_total),
+        );
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(owner_id, &approved_account_ids);
+        }
+        for (account_id, amount) in royalties {
+            if amount.0 > 0 {
+                Promise::new(account_id).transfer(amount.0);
+            }
+        }
+    }
+
+    /// Registers the caller's ed25519 public key for signing transfer permits, replacing any
+    /// earlier key. The caller pays for its storage; the rest of the deposit is refunded. At least
+    /// 1 yoctoNEAR is required even when replacing a key, so a function-call access key can't
+    /// swap in its own permit key.
+    #[payable]
+    pub fn register_permit_key(&mut self, public_key: Vec<u8>) {
+        assert!(env::attached_deposit() >= 1, "{}", ERR_ONE_YOCTO_REQUIRED);
+        assert_eq!(public_key.len(), 32, "{}", ERR_PERMIT_KEY_LENGTH);
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let last_nonce = self.permit_keys.get(&account_id).map_or(0, |(_, last_nonce)| last_nonce);
+        self.permit_keys.insert(&account_id, &(public_key, last_nonce));
+        refund_unused_deposit(0, initial_storage_usage);
+    }
+
+    /// Returns the account's permit key and the last nonce it used, which the next permit must
+    /// exceed.
+    pub fn permit_key(&self, account_id: AccountId) -> Option<(Vec<u8>, u64)> {
+        self.permit_keys.get(&account_id)
+    }
+
+    /// Transfers a ticket on its owner's behalf, so a relayer can pay for gasless transfers.
+    /// `signature` is the owner's ed25519 signature, with their registered permit key, over
+    /// `"{token_id}:{receiver_id}:{nonce}:{deadline_ns}:{contract_account_id}"`. Each permit
+    /// needs a `nonce` above the owner's last one, so it can't be replayed, and is rejected
+    /// after `deadline_ns`. The relayer's deposit covers the transfer like for `nft_transfer`.
+    #[payable]
+    pub fn nft_transfer_with_permit(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        signature: Vec<u8>,
+        nonce: u64,
+        deadline_ns: u64,
+    ) {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_TOKEN_NOT_FOUND);
+        let (public_key, last_nonce) = self.permit_keys.get(&owner_id).expect(ERR_PERMIT_KEY_NOT_SET);
+        assert!(env::block_timestamp() <= deadline_ns, "{}", ERR_PERMIT_EXPIRED);
+        assert!(nonce > last_nonce, "{}", ERR_PERMIT_NONCE_USED);
+        let message = format!("{}:{}:{}:{}:{}", token_id, receiver_id, nonce, deadline_ns, env::current_account_id());
+        assert!(
+            verify_ed25519(&public_key, message.as_bytes(), &signature),
+            "{}",
+            ERR_INVALID_SIGNATURE
+        );
+        self.permit_keys.insert(&owner_id, &(public_key, nonce));
+
+        let storage_deposit = self.assert_transfer_deposit(self.transfer_fee_on_plain_transfers);
+        let (owner_id, approved_account_ids) =
+            self.internal_transfer(&owner_id, &receiver_id, &token_id, None, None, Some(storage_deposit));
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(owner_id, &approved_account_ids);
+        }
+    }
+}
+
+impl Contract {
+    fn assert_redemptions_unlocked(&self) {
+        assert!(!self.redemptions_locked, "{}", RedeemError::Locked);
+    }
+
+    /// Approves `account_id` for `token_id` on behalf of its owner, the caller, and returns the
+    /// owner, the new approval id and the storage the approval added. Re-approving an account
+    /// only bumps its approval id, so no new storage is used.
+    fn internal_approve(&mut self, token_id: &TokenId, account_id: &AccountId) -> (AccountId, u64, u64) {
+        let owner_id = self.tokens.owner_by_id.get(token_id).expect(ERR_UNKNOWN_TOKEN);
+        assert_eq!(env::predecessor_account_id(), owner_id, "{}", ERR_PREDECESSOR_NOT_OWNER);
+        self.assert_not_blocked(&owner_id);
+        assert!(
+            self.allowed_marketplaces.is_empty() || self.allowed_marketplaces.contains(account_id),
+            "{}",
+            ERR_MARKETPLACE_NOT_ALLOWLISTED
+        );
+
+        let approvals_by_id = self.tokens.approvals_by_id.as_mut().unwrap();
+        let next_approval_id_by_id = self.tokens.next_approval_id_by_id.as_mut().unwrap();
+        let mut approved_account_ids = approvals_by_id.get(token_id).unwrap_or_default();
+        assert!(
+            approved_account_ids.contains_key(account_id)
+                || approved_account_ids.len() < self.max_approvals_per_token as usize,
+            "{}",
+            ERR_TOO_MANY_APPROVALS
+        );
+        let approval_id = next_approval_id_by_id.get(token_id).unwrap_or(1);
+        let old_approval_id = approved_account_ids.insert(account_id.clone(), approval_id);
+
+        approvals_by_id.insert(token_id, &approved_account_ids);
+        next_approval_id_by_id.insert(token_id, &(approval_id + 1));
+
+        let storage_used = if old_approval_id.is_none() {
+            bytes_for_approved_account_id(account_id)
+        } else {
+            0
+        };
+        (owner_id, approval_id, storage_used)
+    }
+
+    fn assert_not_refunding(&self, token_id: &TokenId) {
+        assert!(!self.refunds_in_progress.contains(token_id), "{}", ERR_REFUND_IN_PROGRESS);
+    }
+
+    /// Whether the ticket sits in the resale pool. `consign` records the consignment just
+    /// before handing the ticket to the owner, so it only counts once the holder gave it up.
+    fn is_consigned(&self, token_id: &TokenId) -> bool {
+        self.consignments
+            .get(token_id)
+            .is_some_and(|(holder_id, _)| self.tokens.owner_by_id.get(token_id) != Some(holder_id))
+    }
+
+    /// Keeps consigned tickets where they are until `resell_consigned` or `cancel_consignment`
+    /// takes them out of the pool.
+    fn assert_not_consigned(&self, token_id: &TokenId) {
+        assert!(!self.is_consigned(token_id), "{}", ERR_CONSIGNED);
+    }
+
+    fn is_minting_closed(&self) -> bool {
+        self.mint_end_ns.is_some_and(|mint_end_ns| env::block_timestamp() >= mint_end_ns)
+    }
+
+    fn assert_not_blocked(&self, account_id: &AccountId) {
+        assert!(!self.blocked_accounts.contains(account_id), "{}", fill(ERR_ACCOUNT_BLOCKED, &[account_id]));
+    }
+
+    fn assert_supply_invariant(&self) {
+        assert!(self.supply_invariant_ok(), "{}", ERR_SUPPLY_INVARIANT);
+    }
+
+    /// Guards the methods that touch money or the collection itself: only the contract owner
+    /// may call them.
+    fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.tokens.owner_id, "{}", ERR_NOT_OWNER);
+    }
+
+    /// Guards day-to-day operational methods, which any admin may call as well as the owner.
+    fn assert_admin_or_owner(&self) {
+        assert!(self.is_admin_or_owner(&env::predecessor_account_id()), "{}", ERR_NOT_ADMIN_OR_OWNER);
+    }
+
+    fn is_admin_or_owner(&self, account_id: &AccountId) -> bool {
+        account_id == &self.tokens.owner_id || self.admins.contains(account_id)
+    }
+
+    /// Requires the transfer fee plus the usual 1 yoctoNEAR when `charge_fee` is set and a fee
+    /// is configured, and 1 yoctoNEAR otherwise. The fee is added to the withdrawable proceeds.
+    /// Returns the rest of the deposit, which pays for the transfer's history storage.
+    fn assert_transfer_deposit(&mut self, charge_fee: bool) -> Balance {
+        let transfer_fee = self.transfer_fee.filter(|_| charge_fee).unwrap_or(0);
+        assert!(
+            env::attached_deposit() > transfer_fee,
+            "{}",
+            fill(ERR_TRANSFER_FEE_DEPOSIT, &[&transfer_fee])
+        );
+        self.proceeds += transfer_fee;
+        env::attached_deposit() - transfer_fee - 1
+    }
+
+    /// Transfers a token through every path (`nft_transfer`, `nft_transfer_call` and
+    /// `nft_transfer_payout`), applying the contract's transfer restrictions first. The memo
+    /// and history record are paid from the sender's storage balance if it covers them, or
+    /// else from `storage_deposit`, the deposit left after `assert_transfer_deposit`. With no
+    /// `storage_deposit` the contract pays for them.
+    fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        storage_deposit: Option<Balance>,
+    ) -> (AccountId, Option<HashMap<AccountId, u64>>) {
+        assert!(!token_id.starts_with(POAP_TOKEN_PREFIX), "{}", ERR_ATTENDANCE_TOKEN_TRANSFER);
+        if self.lock_transfer_after_redeem {
+            assert!(!self.is_redeemed(token_id.clone()), "{}", ERR_REDEEMED_TRANSFER_LOCKED);
+        }
+        self.assert_not_refunding(token_id);
+        self.assert_not_consigned(token_id);
+        // The sender is an approved marketplace rather than the holder when selling on approval.
+        if let Some(owner_id) = self.tokens.owner_by_id.get(token_id) {
+            self.assert_not_blocked(&owner_id);
+        }
+        self.assert_not_blocked(sender_id);
+        self.assert_not_blocked(receiver_id);
+        if let Some(max_held) = self.max_held_per_account {
+            assert!(self.tokens_held(receiver_id) < max_held, "{}", ERR_RECIPIENT_HOLDS_TOO_MANY);
+        }
+        if sender_id != &self.tokens.owner_id {
+            assert!(env::block_timestamp() >= self.transferable_at(token_id.clone()), "{}", ERR_TRANSFER_COOLDOWN);
+        }
+
+        let memo = memo.map(|memo| memo.chars().take(MAX_STORED_MEMO_LEN).collect::<String>());
+        let transfer = self.tokens.internal_transfer(sender_id, receiver_id, token_id, approval_id, memo.clone());
+
+        let initial_storage_usage = env::storage_usage();
+        if let Some(memo) = &memo {
+            self.transfer_memos.insert(token_id, memo);
+        }
+        self.record_transfer(token_id, &transfer.0, receiver_id, memo);
+        let unpaid_storage_from = self.draw_storage_balance(sender_id, initial_storage_usage);
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - unpaid_storage_from);
+        if let Some(storage_deposit) = storage_deposit {
+            assert!(
+                storage_deposit >= storage_cost,
+                "{}",
+                fill(ERR_TRANSFER_HISTORY_DEPOSIT, &[&(storage_cost - storage_deposit)])
+            );
+            if storage_deposit > storage_cost {
+                Promise::new(env::predecessor_account_id()).transfer(storage_deposit - storage_cost);
+            }
+        }
+        transfer
+    }
+
+    /// Remembers the payout of an `nft_transfer_payout` sale for `confirm_payout`, replacing
+    /// any unconfirmed one of an earlier sale of the token. Returns the storage cost, which
+    /// `storage_deposit` must cover if given.
+    fn record_pending_payout(
+        &mut self,
+        token_id: &TokenId,
+        marketplace_id: &AccountId,
+        payout: &Payout,
+        storage_deposit: Option<Balance>,
+    ) -> Balance {
+        let initial_storage_usage = env::storage_usage();
+        let amounts = payout.payout.iter().map(|(account_id, amount)| (account_id.clone(), amount.0)).collect();
+        self.pending_payouts.insert(token_id, &(marketplace_id.clone(), amounts));
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        if let Some(storage_deposit) = storage_deposit {
+            assert!(
+                storage_deposit >= storage_cost,
+                "{}",
+                fill(ERR_PAYOUT_RECORD_DEPOSIT, &[&(storage_cost - storage_deposit)])
+            );
+        }
+        storage_cost
+    }
+
+    fn record_transfer(&mut self, token_id: &TokenId, from: &AccountId, to: &AccountId, memo: Option<String>) {
+        let mut history = self.transfer_history.get(token_id).unwrap_or_else(|| {
+            Vector::new(StorageKey::TransferHistoryEntries { token_id_hash: env::sha256_array(token_id.as_bytes()) })
+        });
+        history.push(&TransferRecord {
+            from: from.clone(),
+            to: to.clone(),
+            timestamp: env::block_timestamp(),
+            memo,
+        });
+        self.transfer_history.insert(token_id, &history);
+    }
+
+    fn tokens_held(&self, account_id: &AccountId) -> u64 {
+        self.tokens
+            .tokens_per_owner
+            .as_ref()
+            .unwrap()
+            .get(account_id)
+            .map_or(0, |token_ids| token_ids.len())
+    }
+
+    /// Returns the token if `caller_id` can redeem it right now, or the reason it can't.
+    fn check_redeemable(&self, token_id: &TokenId, caller_id: &AccountId) -> Result<Token, RedeemError> {
+        let token = self.tokens.nft_token(token_id.clone()).ok_or(RedeemError::NotFound)?;
+        if &token.owner_id != caller_id {
+            return Err(RedeemError::NotOwner);
+        }
+        if self.refunds_in_progress.contains(token_id) {
+            return Err(RedeemError::RefundInProgress);
+        }
+        if self.is_consigned(token_id) {
+            return Err(RedeemError::Consigned);
+        }
+        check_ticket_validity(token.metadata.as_ref().unwrap(), self.redemption_grace_ns)?;
+        Ok(token)
+    }
+
+    /// Keeps the redeemed counter of the ticket's tier, if it has one, in step with `redeemed_count`.
+    fn count_tier_redemption(&mut self, attributes: &[(String, String)], redeemed: bool) {
+        let tier = match get_attribute(attributes, TIER_TRAIT) {
+            Some(tier) => tier.to_string(),
+            None => return,
+        };
+        if let Some(mut tier_state) = self.tiers.get(&tier) {
+            if redeemed {
+                tier_state.redeemed += 1;
+            } else {
+                tier_state.redeemed -= 1;
+            }
+            self.tiers.insert(&tier, &tier_state);
+        }
+    }
+
+    /// Removes a ticket and everything kept about it, and emits the burn event. Returns the
+    /// value of the released storage, which the caller refunds.
+    fn internal_burn(&mut self, token_id: &TokenId, owner_id: &AccountId) -> Balance {
+        self.assert_not_consigned(token_id);
+        let initial_storage_usage = env::storage_usage();
+        self.tokens.owner_by_id.remove(token_id);
+        if token_id.starts_with(POAP_TOKEN_PREFIX) {
+            self.poaps_burned += 1;
+        } else {
+            self.burned_tokens += 1;
+        }
+        if is_sequential_token_id(token_id) {
+            self.burned_ticket_ids.insert(token_id);
+        }
+        let token_metadata = self.tokens.token_metadata_by_id.as_mut().and_then(|by_id| by_id.remove(token_id));
+        if let Some(token_metadata) = token_metadata {
+            let attributes = extra_to_attributes(&token_metadata.extra);
+            if get_attribute(&attributes, REDEEMED_TRAIT) == Some("true") {
+                self.redeemed_count -= 1;
+                self.count_tier_redemption(&attributes, false);
+            }
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut owner_tokens = tokens_per_owner.get(owner_id).unwrap();
+            owner_tokens.remove(token_id);
+            if owner_tokens.is_empty() {
+                tokens_per_owner.remove(owner_id);
+            } else {
+                tokens_per_owner.insert(owner_id, &owner_tokens);
+            }
+        }
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(token_id);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+        self.minted_at.remove(token_id);
+        self.redeemed_index.remove(token_id);
+        self.transfer_memos.remove(token_id);
+        if let Some(mut history) = self.transfer_history.remove(token_id) {
+            history.clear();
+        }
+        self.paid_prices.remove(token_id);
+
+        NftBurn { owner_id, token_ids: &[token_id], authorized_id: None, memo: None }.emit();
+        let released_storage = initial_storage_usage.saturating_sub(env::storage_usage());
+        Balance::from(released_storage) * env::storage_byte_cost()
+    }
+
+    /// Uses up one of the ticket's redemptions. The ticket only counts as redeemed once all of
+    /// them are used, so multi-entry tickets keep passing `check_ticket_validity` until then.
+    fn internal_redeem(&mut self, mut token: Token) -> Token {
+        let token_metadata = token.metadata.as_mut().unwrap();
+        let mut attributes = extra_to_attributes(&token_metadata.extra);
+        let (used, max) = redemption_counts(&attributes);
+        let used = used + 1;
+        if max > 1 {
+            set_attribute(&mut attributes, REDEMPTIONS_USED_TRAIT, &used.to_string());
+        }
+        if used == max {
+            set_attribute(&mut attributes, REDEEMED_TRAIT, "true");
+            self.redeemed_index.insert(&token.token_id, &true);
+            self.redeemed_count += 1;
+            self.count_tier_redemption(&attributes, true);
+        }
+        token_metadata.extra = Some(attributes_to_extra(&attributes));
+
+        self.tokens.token_metadata_by_id.as_mut().unwrap().insert(&token.token_id, token_metadata);
+        self.mint_attendance_token(&token.token_id, &token.owner_id);
+        token
+    }
+
+    /// Mints the attendance token for a just-redeemed ticket if `poap_metadata` is set. This is
+    /// best-effort so it can never block entry: it's skipped if the ticket already earned one
+    /// or the contract can't spare the storage, which it pays for.
+    fn mint_attendance_token(&mut self, ticket_id: &TokenId, owner_id: &AccountId) {
+        let poap_metadata = match &self.poap_metadata {
+            Some(poap_metadata) => poap_metadata.clone(),
+            None => return,
+        };
+        let poap_id = format!("{}{}", POAP_TOKEN_PREFIX, ticket_id);
+        if self.tokens.owner_by_id.contains_key(&poap_id) {
+            return;
+        }
+        let required_balance =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() + MIN_STORAGE_BALANCE_BYTES);
+        if env::account_balance() < required_balance {
+            env::log_str(&format!("Skipping attendance token for ticket {}: contract balance too low", ticket_id));
+            return;
+        }
+
+        let poap = self.tokens.internal_mint_with_refund(poap_id, owner_id.clone(), Some(poap_metadata), None);
+        self.poaps_minted += 1;
+        NftMint { owner_id: &poap.owner_id, token_ids: &[&poap.token_id], memo: None }.emit();
+    }
+
+    /// Splits `balance` between the royalty recipients and the owner. The payout never has more
+    /// than `max_len_payout` receivers: if there are too many royalties, only the largest
+    /// `max_len_payout - 1` are paid (ties broken by account id) and the owner keeps the rest, so
+    /// a marketplace's limit can't make the sale fail.
+    ///
+    /// Each royalty is rounded down and the owner gets exactly what's left of `balance`, so the
+    /// amounts always add up to `balance` and the rounding dust goes to the seller. A zero
+    /// `balance` is rejected, as it would let a sale through with no royalties paid.
+    fn internal_payout(&self, owner_id: &AccountId, balance: U128, max_len_payout: u32) -> Payout {
+        assert!(max_len_payout > 0, "{}", ERR_PAYOUT_MAX_LEN);
+        assert!(balance.0 > 0, "{}", ERR_ZERO_SALE_PRICE);
+
+        let mut payout: HashMap<AccountId, U128> = self
+            .royalty_split(owner_id, max_len_payout as usize - 1)
+            .into_iter()
+            .filter(|(account_id, _)| account_id != owner_id)
+            .map(|(account_id, royalty)| (account_id, royalty_to_payout(royalty, balance.0)))
+            .collect();
+        let royalties_total: u128 = payout.values().map(|amount| amount.0).sum();
+        payout.insert(owner_id.clone(), U128(balance.0 - royalties_total));
+        Payout { payout }
+    }
+
+    /// Returns the basis points of the `max_royalties` largest royalties, plus the owner's
+    /// residual. Royalties owed to the owner themselves are folded into the residual.
+    fn royalty_split(&self, owner_id: &AccountId, max_royalties: usize) -> HashMap<AccountId, u32> {
+        let mut royalties: Vec<(AccountId, u32)> = self
+            .perpetual_royalties
+            .iter()
+            .flatten()
+            .filter(|(account_id, _)| *account_id != owner_id)
+            .map(|(account_id, royalty)| (account_id.clone(), *royalty))
+            .collect();
+        if royalties.len() > max_royalties {
+            royalties.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+            env::log_str(&format!(
+                "Payout limited to {} royalties, skipping {}",
+                max_royalties,
+                royalties.len() - max_royalties
+            ));
+            royalties.truncate(max_royalties);
+        }
+
+        let total_perpetual: u32 = royalties.iter().map(|(_, royalty)| royalty).sum();
+        let mut split: HashMap<AccountId, u32> = royalties.into_iter().collect();
+        split.insert(owner_id.clone(), 10000 - total_perpetual);
+        split
+    }
+
+    /// Mints a free ticket to each receiver, marked with a `true` `marker` attribute. The caller's
+    /// deposit must cover the storage and the rest is refunded.
+    fn internal_mint_free(&mut self, receiver_ids: Vec<AccountId>, marker: &str) -> Vec<Token> {
+        assert!(self.tokens_left() >= receiver_ids.len() as u64, "{}", ERR_NOT_ENOUGH_TICKETS);
+
+        let initial_storage_usage = env::storage_usage();
+        let tokens: Vec<Token> = receiver_ids
+            .into_iter()
+            .map(|receiver_id| {
+                self.internal_mint_ticket(
+                    receiver_id,
+                    vec![
+                        (REDEEMED_TRAIT.to_string(), "false".to_string()),
+                        (marker.to_string(), "true".to_string()),
+                    ],
+                )
+            })
+            .collect();
+        refund_unused_deposit(0, initial_storage_usage);
+
+        let token_ids: Vec<&str> = tokens.iter().map(|token| token.token_id.as_str()).collect();
+        let mints: Vec<NftMint> = tokens
+            .iter()
+            .zip(&token_ids)
+            .map(|(token, token_id)| NftMint {
+                owner_id: &token.owner_id,
+                token_ids: std::slice::from_ref(token_id),
+                memo: None,
+            })
+            .collect();
+        if !mints.is_empty() {
+            NftMint::emit_many(&mints);
+        }
+        tokens
+    }
+
+    /// Mints a bought ticket at `price` and charges the caller for it. The request id, referral
+    /// earnings and allowlist nonce are recorded before the storage is measured, so the buyer
+    /// pays for them. See `prepare_purchase` for the two attribute lists.
+    fn internal_buy(
+        &mut self,
+        receiver_id: AccountId,
+        extra_attributes: Option<Vec<(String, String)>>,
+        contract_attributes: Vec<(String, String)>,
+        price: u128,
+        (valid_from_ns, valid_until_ns): (Option<u64>, Option<u64>),
+        (request_id, referrer, allowlist_nonce): (Option<String>, Option<AccountId>, Option<u64>),
+    ) -> Token {
+        assert!(env::attached_deposit() >= price, "{}", fill(ERR_INSUFFICIENT_DEPOSIT, &[&price]));
+
+        let initial_storage_usage = env::storage_usage();
+        let attributes = self.prepare_purchase(&env::predecessor_account_id(), extra_attributes, contract_attributes);
+        let gift_message = get_attribute(&attributes, GIFT_MESSAGE_TRAIT).map(str::to_string);
+        let mut token = self.internal_mint_ticket(receiver_id, attributes);
+        if valid_from_ns.is_some() || valid_until_ns.is_some() {
+            let token_metadata = token.metadata.as_mut().unwrap();
+            if let Some(valid_from_ns) = valid_from_ns {
+                token_metadata.starts_at = Some(valid_from_ns.to_string());
+            }
+            if let Some(valid_until_ns) = valid_until_ns {
+                token_metadata.expires_at = Some(valid_until_ns.to_string());
+            }
+            self.tokens.token_metadata_by_id.as_mut().unwrap().insert(&token.token_id, token_metadata);
+        }
+        self.total_revenue += price;
+        self.proceeds += price;
+        self.paid_prices.insert(&token.token_id, &price);
+        self.pay_primary_split(price);
+        if let Some(request_id) = request_id {
+            self.record_buy_request(request_id, &token.token_id);
+        }
+        if let Some(allowlist_nonce) = allowlist_nonce {
+            self.allowlist_nonces.insert(&token.owner_id, &allowlist_nonce);
+        }
+        if let (Some(referrer), Some(referral_bps)) = (referrer, self.referral_bps) {
+            let referral = royalty_to_payout(referral_bps, price).0;
+            let earnings = self.referral_earnings.get(&referrer).unwrap_or(0);
+            self.referral_earnings.insert(&referrer, &(earnings + referral));
+            self.proceeds -= referral;
+            Promise::new(referrer).transfer(referral);
+        }
+        // The minting price stays on the contract; only what's left after storage is refunded.
+        let unpaid_storage_from = self.draw_storage_balance(&env::predecessor_account_id(), initial_storage_usage);
+        refund_unused_deposit(price, unpaid_storage_from);
+
+        NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: gift_message.as_deref() }.emit();
+        token
+    }
+
+    /// Remembers the caller's latest `MAX_BUY_REQUESTS_PER_ACCOUNT` request ids so retries return
+    /// the ticket they bought. Called within the buy's storage accounting, so the buyer pays for it.
+    fn record_buy_request(&mut self, request_id: String, token_id: &TokenId) {
+        let caller_id = env::predecessor_account_id();
+        let mut buy_requests = self.buy_requests.get(&caller_id).unwrap_or_default();
+        if buy_requests.len() == MAX_BUY_REQUESTS_PER_ACCOUNT {
+            buy_requests.remove(0);
+        }
+        buy_requests.push((request_id, token_id.clone()));
+        self.buy_requests.insert(&caller_id, &buy_requests);
+    }
+
+    /// Checks shared by every purchase path: rejects blocked buyers, counts the buy against
+    /// `max_buys_per_block` and builds the ticket's attributes, rolling the rarity trait when
+    /// `random_traits` is set. The buyer's `extra_attributes` can't use any `RESERVED_TRAITS`;
+    /// `contract_attributes` are the reserved ones the calling method sets itself, like the
+    /// tier. Call it within the buy's storage accounting, since it writes the per-block buy count.
+    fn prepare_purchase(
+        &mut self,
+        buyer_id: &AccountId,
+        extra_attributes: Option<Vec<(String, String)>>,
+        contract_attributes: Vec<(String, String)>,
+    ) -> Vec<(String, String)> {
+        let mut attributes = vec![(REDEEMED_TRAIT.to_string(), "false".to_string())];
+        for (trait_type, value) in extra_attributes.unwrap_or_default() {
+            assert!(
+                !RESERVED_TRAITS.contains(&trait_type.as_str()),
+                "{}",
+                fill(ERR_ATTRIBUTE_RESERVED, &[&trait_type])
+            );
+            attributes.push((trait_type, value));
+        }
+        attributes.extend(contract_attributes);
+        if let Some(weights) = &self.random_traits {
+            let roll = random_roll(self.minted_tokens);
+            attributes.push((RANDOM_TRAIT.to_string(), pick_weighted_trait(weights, roll).to_string()));
+        }
+        self.assert_not_blocked(buyer_id);
+        self.count_buy_in_block(buyer_id);
+        attributes
+    }
+
+    /// Records a buy by `buyer_id` in the current block, rejecting it once they already bought
+    /// `max_buys_per_block` tickets in this block.
+    fn count_buy_in_block(&mut self, buyer_id: &AccountId) {
+        let max_buys_per_block = match self.max_buys_per_block {
+            Some(max_buys_per_block) => max_buys_per_block,
+            None => return,
+        };
+        let block_height = env::block_height();
+        let buys = match self.buys_in_block.get(buyer_id) {
+            Some((last_block_height, buys)) if last_block_height == block_height => buys,
+            _ => 0,
+        };
+        assert!(buys < max_buys_per_block, "{}", ERR_TOO_MANY_BUYS_IN_BLOCK);
+        self.buys_in_block.insert(buyer_id, &(block_height, buys + 1));
+    }
+
+    /// Pays each `primary_split` partner their share of a primary sale at `price` out of the proceeds.
+    fn pay_primary_split(&mut self, price: Balance) {
+        if let Some(primary_split) = &self.primary_split {
+            for (account_id, share_bps) in primary_split {
+                let share = royalty_to_payout(*share_bps, price).0;
+                if share > 0 {
+                    self.proceeds -= share;
+                    Promise::new(account_id.clone()).transfer(share);
+                }
+            }
+        }
+    }
+
+    /// Mints a ticket to each waitlisted account in turn while supply is left. Once minting has
+    /// closed the waitlist is no longer served, and waiting accounts can leave to get their
+    /// deposit back.
+    fn serve_waitlist(&mut self) {
+        while self.tokens_left() > 0 && !self.is_minting_closed() && self.serve_next_on_waitlist(None) {}
+    }
+
+    /// Offers the seat of a refunded ticket to the first account on the waitlist by reissuing
+    /// the ticket's id to them, unless minting has closed.
+    fn offer_freed_seat(&mut self, token_id: &TokenId) {
+        if !self.is_minting_closed() && self.burned_ticket_ids.contains(token_id) {
+            self.serve_next_on_waitlist(Some(token_id.clone()));
+        }
+    }
+
+    /// Mints a ticket to the first waitlisted account that isn't blocked, reissuing `freed_id` if
+    /// given, and pays the price and storage from their held deposit, refunding the rest.
+    /// Blocked accounts ahead of them get their deposit back. Returns `false` if nobody was left
+    /// to serve.
+    fn serve_next_on_waitlist(&mut self, freed_id: Option<TokenId>) -> bool {
+        let (account_id, deposit) = loop {
+            if self.waitlist.is_empty() {
+                return false;
+            }
+            let (account_id, deposit) = self.remove_waitlist_entry(0);
+            if !self.blocked_accounts.contains(&account_id) {
+                break (account_id, deposit);
+            }
+            Promise::new(account_id).transfer(deposit);
+        };
+
+        let initial_storage_usage = env::storage_usage();
+        let attributes = vec![(REDEEMED_TRAIT.to_string(), "false".to_string())];
+        let token = match freed_id {
+            Some(token_id) => self.internal_reissue_ticket(token_id, account_id.clone(), attributes),
+            None => self.internal_mint_ticket(account_id.clone(), attributes),
+        };
+        self.total_revenue += self.minting_price;
+        self.proceeds += self.minting_price;
+        self.paid_prices.insert(&token.token_id, &self.minting_price);
+        self.pay_primary_split(self.minting_price);
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        let storage_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        let refund = deposit.saturating_sub(self.minting_price + storage_cost);
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+        NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: None }.emit();
+        true
+    }
+
+    /// Mints a ticket at the id of a burned ticket, which then no longer counts as burned.
+    fn internal_reissue_ticket(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        attributes: Vec<(String, String)>,
+    ) -> Token {
+        assert!(self.burned_ticket_ids.remove(&token_id), "{}", ERR_TOKEN_ID_NOT_FREED);
+        self.burned_tokens = self.burned_tokens.checked_sub(1).expect(ERR_BURNED_COUNT_OUT_OF_SYNC);
+        self.internal_mint_ticket_at(token_id, receiver_id, attributes)
+    }
+
+    /// Removes the waitlist entry at `index`, keeping the others in order.
+    fn remove_waitlist_entry(&mut self, index: u64) -> (AccountId, Balance) {
+        let entry = self.waitlist.get(index).unwrap();
+        for next in index + 1..self.waitlist.len() {
+            self.waitlist.replace(next - 1, &self.waitlist.get(next).unwrap());
+        }
+        self.waitlist.pop();
+        entry
+    }
+
+    /// Counts a use of `code` and returns the discounted minting price.
+    fn use_promo_code(&mut self, code: &String) -> u128 {
+        let mut promo_code = self.promo_codes.get(code).expect(ERR_UNKNOWN_PROMO_CODE);
+        assert!(
+            promo_code.max_uses.is_none_or(|max_uses| promo_code.uses < max_uses),
+            "{}",
+            ERR_PROMO_CODE_EXHAUSTED
+        );
+        promo_code.uses += 1;
+        self.promo_codes.insert(code, &promo_code);
+        self.minting_price * u128::from(10000 - promo_code.discount_bps) / 10000
+    }
+
+    /// Pays for the storage used since `initial_storage_usage` out of `account_id`'s
+    /// `storage_deposit` balance if it covers it. Returns the storage usage from which the
+    /// attached deposit still has to pay, which is the current usage if the balance paid.
+    fn draw_storage_balance(&mut self, account_id: &AccountId, initial_storage_usage: u64) -> u64 {
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        let min_balance = self.storage_balance_bounds().min.0;
+        match self.storage_balances.get(account_id) {
+            Some(balance) if balance >= min_balance + storage_cost => {
+                self.storage_balances.insert(account_id, &(balance - storage_cost));
+                env::storage_usage()
+            }
+            _ => initial_storage_usage,
+        }
+    }
+
+    fn storage_balance(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        let min_balance = self.storage_balance_bounds().min.0;
+        self.storage_balances.get(account_id).map(|balance| StorageBalance {
+            total: U128(balance),
+            available: U128(balance.saturating_sub(min_balance)),
+        })
+    }
+
+    /// Formats a sequential ticket id, zero-padded if the contract was initialized with
+    /// `pad_token_ids`.
+    fn format_token_id(&self, id: u64) -> TokenId {
+        format!("{:0width$}", id, width = self.token_id_width)
+    }
+
+    /// Mints the next sequential ticket from the template, or its tier's if it has one, with the
+    /// given `extra` attributes.
+    /// Callers are responsible for charging the deposit and emitting the mint event.
+    fn internal_mint_ticket(&mut self, receiver_id: AccountId, attributes: Vec<(String, String)>) -> Token {
+        assert!(!self.is_minting_closed(), "{}", ERR_MINTING_CLOSED);
+        assert!(self.minted_tokens < self.max_supply, "{}", ERR_SOLD_OUT);
+
+        let token_id = self.format_token_id(self.minted_tokens + 1);
+        self.minted_tokens += 1;
+        self.assert_supply_invariant();
+        self.internal_mint_ticket_at(token_id, receiver_id, attributes)
+    }
+
+    /// Mints a ticket at `token_id` from the template, or its tier's if it has one, without
+    /// touching the supply counters.
+    fn internal_mint_ticket_at(
+        &mut self,
+        token_id: TokenId,
+        receiver_id: AccountId,
+        mut attributes: Vec<(String, String)>,
+    ) -> Token {
+        assert_valid_receiver(&receiver_id);
+        self.assert_not_blocked(&receiver_id);
+        if self.max_redemptions > 1 {
+            set_attribute(&mut attributes, MAX_REDEMPTIONS_TRAIT, &self.max_redemptions.to_string());
+            set_attribute(&mut attributes, REDEMPTIONS_USED_TRAIT, "0");
+        }
+
+        self.minted_at.insert(&token_id, &env::block_timestamp());
+        self.redeemed_index.insert(&token_id, &false);
+
+        let tier_metadata = get_attribute(&attributes, TIER_TRAIT)
+            .and_then(|tier| self.tiers.get(&tier.to_string()))
+            .and_then(|tier| tier.metadata);
+        let template = tier_metadata.as_ref().unwrap_or(&self.token_metadata);
+        let mut token_metadata = TokenMetadata { 
+            title:  template.title.clone(), 
+            description: template.description.clone(), 
+            media: template.media.clone(), 
+            media_hash: template.media_hash.clone(), 
+            copies: template.copies, 
+            issued_at: template.issued_at.clone(), 
+            expires_at: template.expires_at.clone(), 
+            starts_at: template.starts_at.clone(), 
+            updated_at: template.updated_at.clone(), 
+            extra: Some(attributes_to_extra(&attributes)),
+            reference: template.reference.clone(), 
+            reference_hash: template.reference_hash.clone() 
+        };
+        if self.minimal_metadata {
+            token_metadata.description = None;
+            token_metadata.media_hash = None;
+            token_metadata.copies = None;
+            token_metadata.issued_at = None;
+            token_metadata.updated_at = None;
+            token_metadata.reference = None;
+            token_metadata.reference_hash = None;
+        }
+        self.tokens.internal_mint_with_refund(token_id, receiver_id, Some(token_metadata), None)
+    }
+}
+
+/// Rejects receivers that parse as account ids but can't be what the sender meant, so a typo
+/// doesn't mint a ticket to an account nobody controls. A dot-less id made only of hex digits,
+/// or one of exactly 64 characters, is meant as an implicit account, so it must be both: a
+/// character too many or too few is the usual slip when pasting one. The contract's own
+/// account is rejected too: it has no way to transfer the ticket back out.
+fn assert_valid_receiver(receiver_id: &AccountId) {
+    let id = receiver_id.as_str();
+    assert!(!id.is_empty(), "{}", ERR_EMPTY_RECEIVER);
+    assert_ne!(receiver_id, &env::current_account_id(), "{}", ERR_MINT_TO_CONTRACT);
+    let all_hex = id.bytes().all(|byte| byte.is_ascii_hexdigit());
+    let looks_implicit = !id.contains('.') && (all_hex || id.len() == 64);
+    assert!(
+        !looks_implicit || (all_hex && id.len() == 64),
+        "{}",
+        fill(ERR_INVALID_IMPLICIT_RECEIVER, &[&id])
+    );
+}
+
+/// Returns a pseudo-random number for the `nonce`th draw. The block's random seed is the same
+/// for every call in a receipt, so it's hashed with the nonce to tell batched buys apart.
+fn random_roll(nonce: u64) -> u64 {
+    let mut input = env::random_seed();
+    input.extend_from_slice(&nonce.to_le_bytes());
+    let mut roll = [0; 8];
+    roll.copy_from_slice(&env::sha256(&input)[..8]);
+    u64::from_le_bytes(roll)
+}
+
+/// Picks the trait `roll` lands on, each trait taking a share of the rolls proportional to its
+/// weight.
+fn pick_weighted_trait(weights: &[(String, u32)], roll: u64) -> &str {
+    let total: u64 = weights.iter().map(|(_, weight)| u64::from(*weight)).sum();
+    let mut roll = roll % total;
+    for (name, weight) in weights {
+        if roll < u64::from(*weight) {
+            return name;
+        }
+        roll -= u64::from(*weight);
+    }
+    unreachable!()
+}
+
+/// Returns whether `token_id` is in the namespace of the sequential ticket ids: a positive
+/// number, possibly zero-padded.
+fn is_sequential_token_id(token_id: &str) -> bool {
+    token_id.parse::<u64>().is_ok_and(|id| id >= 1)
+}
+
+/// Returns the lowercase hex sha256 of a claim code, the form codes are stored in.
+fn hash_claim_code(code: &str) -> String {
+    env::sha256(code.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Requires the attached deposit to cover `cost` plus the storage used since
+/// `initial_storage_usage`, and refunds the rest to the predecessor.
+fn refund_unused_deposit(cost: u128, initial_storage_usage: u64) {
+    let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+    let required_deposit = cost + storage_cost;
+    let attached_deposit = env::attached_deposit();
+    assert!(
+        attached_deposit >= required_deposit,
+        "{}",
+        fill(ERR_BUY_DEPOSIT, &[&required_deposit])
+    );
+    let refund = attached_deposit - required_deposit;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+/// Requires the primary split and referral share to add up to at most the whole price, so paying
+/// them out of a sale never takes more than the sale brought in.
+fn assert_primary_shares_within_price(primary_split: &Option<HashMap<AccountId, u32>>, referral_bps: Option<u32>) {
+    let split_total: u64 = primary_split
+        .iter()
+        .flat_map(|split| split.values())
+        .map(|bps| *bps as u64)
+        .sum();
+    let total = split_total + referral_bps.unwrap_or(0) as u64;
+    assert!(total <= 10000, "{}", ERR_PRIMARY_SHARES_TOO_HIGH);
+}
+
+/// Requires the royalties to add up to at most `max_total_royalty_bps`, so the seller always
+/// keeps a meaningful share of the sale.
+fn assert_royalties_within_cap(perpetual_royalties: &Option<HashMap<AccountId, u32>>, max_total_royalty_bps: u32) {
+    let total: u64 = perpetual_royalties
+        .iter()
+        .flat_map(|royalties| royalties.values())
+        .map(|bps| *bps as u64)
+        .sum();
+    assert!(
+        total <= max_total_royalty_bps as u64,
+        "{}",
+        fill(ERR_ROYALTIES_ABOVE_CAP, &[&total, &max_total_royalty_bps])
+    );
+}
+
+/// Orders numeric token ids numerically, before any non-numeric ids, which are ordered as strings.
+fn cmp_token_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Caps an enumeration page size at `MAX_LIMIT`, which is also the default.
+fn clamp_limit(limit: Option<u64>) -> u64 {
+    limit.unwrap_or(MAX_LIMIT).min(MAX_LIMIT)
+}
+
+/// Tells the receiver of `nft_transfer_call` whether the ticket was redeemed by adding a
+/// `redeemed` field to a JSON object `msg`. An empty `msg` becomes `{"redeemed": ...}`, and any
+/// other `msg` is passed through unchanged so receivers with their own format keep working.
+fn with_redeemed_flag(msg: String, redeemed: bool) -> String {
+    if msg.is_empty() {
+        return json!({ "redeemed": redeemed }).to_string();
+    }
+    match serde_json::from_str::<serde_json::Value>(&msg) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            fields.insert("redeemed".to_string(), redeemed.into());
+            serde_json::Value::Object(fields).to_string()
+        }
+        _ => msg,
+    }
+}
+
+/// Logs a NEP-297 event for the ticketing-specific actions the NEP-171 events don't cover.
+fn emit_ticket_event(event: &str, data: serde_json::Value) {
+    let event = json!({
+        "standard": TICKET_EVENT_STANDARD,
+        "version": TICKET_EVENT_VERSION,
+        "event": event,
+        "data": [data],
+    });
+    env::log_str(&format!("EVENT_JSON:{}", event));
+}
+
+/// Serializes `(trait_type, value)` pairs into the `{"attributes": [...]}` JSON kept in `extra`.
+fn attributes_to_extra(attributes: &[(String, String)]) -> String {
+    let attributes: Vec<_> = attributes
+        .iter()
+        .map(|(trait_type, value)| json!({"trait_type": trait_type, "value": value}))
+        .collect();
+    json!({"attributes": attributes}).to_string()
+}
+
+/// Parses the `attributes` array of a token's `extra` JSON into `(trait_type, value)` pairs.
+/// `nft_mint` takes `extra` as given, so anything that isn't JSON reads as no attributes rather
+/// than breaking every view and transfer that touches the token.
+fn extra_to_attributes(extra: &Option<String>) -> Vec<(String, String)> {
+    let extra: serde_json::Value = match extra.as_deref().map(serde_json::from_str) {
+        Some(Ok(extra)) => extra,
+        _ => return vec![],
+    };
+    extra["attributes"]
+        .as_array()
+        .map(|attributes| {
+            attributes
+                .iter()
+                .map(|attribute| {
+                    (
+                        attribute["trait_type"].as_str().unwrap_or_default().to_string(),
+                        attribute["value"].as_str().unwrap_or_default().to_string(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// near-sdk 4.1.1 has no `env::ed25519_verify`, so signatures are checked with ed25519-dalek.
+fn verify_ed25519(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let public_key = PublicKey::from_bytes(public_key).expect(ERR_INVALID_ALLOWLIST_SIGNER);
+    match Signature::try_from(signature) {
+        Ok(signature) => public_key.verify(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn get_attribute<'a>(attributes: &'a [(String, String)], trait_type: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == trait_type)
+        .map(|(_, value)| value.as_str())
+}
+
+fn set_attribute(attributes: &mut Vec<(String, String)>, trait_type: &str, value: &str) {
+    match attributes.iter_mut().find(|(key, _)| key == trait_type) {
+        Some(attribute) => attribute.1 = value.to_string(),
+        None => attributes.push((trait_type.to_string(), value.to_string())),
+    }
+}
+
+/// Returns how many of its redemptions a ticket has used and how many it allows. Single-use
+/// tickets only track the `redeemed` flag.
+fn redemption_counts(attributes: &[(String, String)]) -> (u32, u32) {
+    match get_attribute(attributes, MAX_REDEMPTIONS_TRAIT) {
+        Some(max) => (
+            get_attribute(attributes, REDEMPTIONS_USED_TRAIT).map_or(0, |used| used.parse().unwrap_or(0)),
+            max.parse().unwrap_or(1),
+        ),
+        None => (u32::from(get_attribute(attributes, REDEEMED_TRAIT) == Some("true")), 1),
+    }
+}
+
+/// Why a ticket can't be redeemed. Shared by the redeem methods and `nft_is_redeemable` so the
+/// on-chain check and scanners' pre-checks follow the same rules.
+#[derive(Debug, PartialEq)]
+enum RedeemError {
+    Locked,
+    NotFound,
+    NotOwner,
+    AlreadyRedeemed,
+    NotYetValid,
+    Expired,
+    RefundInProgress,
+    Consigned,
+}
+
+impl RedeemError {
+    /// Machine-readable reason returned by `nft_is_redeemable`.
+    fn reason(&self) -> &'static str {
+        match self {
+            RedeemError::Locked => "redemptions_locked",
+            RedeemError::NotFound => "not_found",
+            RedeemError::NotOwner => "not_owner",
+            RedeemError::AlreadyRedeemed => "already_redeemed",
+            RedeemError::NotYetValid => "not_yet_valid",
+            RedeemError::Expired => "expired",
+            RedeemError::RefundInProgress => "refund_in_progress",
+            RedeemError::Consigned => "consigned",
+        }
+    }
+}
+
+impl std::fmt::Display for RedeemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RedeemError::Locked => ERR_REDEMPTIONS_LOCKED,
+            RedeemError::NotFound => ERR_TOKEN_NOT_FOUND,
+            RedeemError::NotOwner => ERR_NOT_TOKEN_OWNER,
+            RedeemError::AlreadyRedeemed => ERR_ALREADY_REDEEMED,
+            RedeemError::NotYetValid => ERR_NOT_YET_VALID,
+            RedeemError::Expired => ERR_EXPIRED,
+            RedeemError::RefundInProgress => ERR_REFUND_IN_PROGRESS,
+            RedeemError::Consigned => ERR_CONSIGNED,
+        })
+    }
+}
+
+/// Checks the holder-independent rules: the ticket is unused and within its validity window,
+/// which extends `grace_ns` past `expires_at`.
+fn check_ticket_validity(token_metadata: &TokenMetadata, grace_ns: u64) -> Result<(), RedeemError> {
+    if get_attribute(&extra_to_attributes(&token_metadata.extra), REDEEMED_TRAIT) != Some("false") {
+        return Err(RedeemError::AlreadyRedeemed);
+    }
+    if is_before_start(token_metadata) {
+        return Err(RedeemError::NotYetValid);
+    }
+    if is_past_expiry(token_metadata, grace_ns) {
+        return Err(RedeemError::Expired);
+    }
+    Ok(())
+}
+
+/// Parses a `TokenMetadata` timestamp field, stored as Unix epoch nanoseconds
+/// so it can be compared against `env::block_timestamp()`.
+fn parse_timestamp(timestamp: &Option<String>) -> Option<u64> {
+    timestamp
+        .as_ref()
+        .map(|value| value.parse().expect(ERR_INVALID_TIMESTAMP))
+}
+
+fn is_before_start(token_metadata: &TokenMetadata) -> bool {
+    parse_timestamp(&token_metadata.starts_at)
+        .is_some_and(|starts_at| env::block_timestamp() < starts_at)
+}
+
+fn is_past_expiry(token_metadata: &TokenMetadata, grace_ns: u64) -> bool {
+    parse_timestamp(&token_metadata.expires_at)
+        .is_some_and(|expires_at| env::block_timestamp() > expires_at.saturating_add(grace_ns))
+}
+
+/// Returns `royalty_percentage` basis points of `amount_to_pay`, rounded down.
+fn royalty_to_payout(royalty_percentage: u32, amount_to_pay: u128) -> U128 {
+    U128(royalty_percentage as u128 * amount_to_pay / 10_000u128)
+}
+
+fn refund_approved_account_ids_iter<'a, I>(
+    account_id: AccountId,
+    approved_account_ids: I, //the approved account IDs must be passed in as an iterator
+) -> Promise
+where
+    I: Iterator<Item = &'a AccountId>,
+{
+    //get the storage total by going through and summing all the bytes for each approved account IDs
+    let storage_released: u64 = approved_account_ids.map(bytes_for_approved_account_id).sum();
+    //transfer the account the storage that is released
+    Promise::new(account_id).transfer(Balance::from(storage_released) * env::storage_byte_cost())
+}
+
+fn refund_approved_account_ids(
+    account_id: AccountId,
+    approved_account_ids: &HashMap<AccountId, u64>,
+) -> Promise {
+    //call the refund_approved_account_ids_iter with the approved account IDs as keys
+    refund_approved_account_ids_iter(account_id, approved_account_ids.keys())
+}
+
+
+#[ext_contract(ext_nft_receiver)]
+pub trait NonFungibleTokenReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+// Transfers are implemented by hand instead of with `impl_non_fungible_token_core!` so that
+// approved-account storage is refunded to the previous owner, like `nft_transfer_payout` does.
+#[near_bindgen]
+impl NonFungibleTokenCore for Contract {
+    #[payable]
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        let storage_deposit = self.assert_transfer_deposit(self.transfer_fee_on_plain_transfers);
+        let sender_id = env::predecessor_account_id();
+        let (owner_id, approved_account_ids) = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            approval_id,
+            memo,
+            Some(storage_deposit),
+        );
+
+        if let Some(approved_account_ids) = approved_account_ids {
+            refund_approved_account_ids(owner_id, &approved_account_ids);
+        }
+    }
+
+    #[payable]
+    fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.nft_transfer_call_with_gas(receiver_id, token_id, approval_id, memo, msg, None)
+    }
+
+    fn nft_token(&self, token_id: TokenId) -> Option<Token> {
+        self.tokens.nft_token(token_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `nft_transfer_call` with the gas reserved for the receiver's `nft_on_transfer` set by the
+    /// caller, for receivers that need more than `default_call_gas`.
+    #[payable]
+    pub fn nft_transfer_call_with_gas(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+        gas_for_call: Option<Gas>,
+    ) -> PromiseOrValue<bool> {
+        let storage_deposit = self.assert_transfer_deposit(self.transfer_fee_on_plain_transfers);
+        let gas_for_call = gas_for_call.unwrap_or(Gas(self.default_call_gas));
+        let sender_id = env::predecessor_account_id();
+        let redeemed = self
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .is_some_and(|token_metadata| {
+                get_attribute(&extra_to_attributes(&token_metadata.extra), REDEEMED_TRAIT) == Some("true")
+            });
+        let (owner_id, approved_account_ids) = self.internal_transfer(
+            &sender_id,
+            &receiver_id,
+            &token_id,
+            approval_id,
+            memo,
+            Some(storage_deposit),
+        );
+
+        assert!(
+            env::prepaid_gas() - env::used_gas() > gas_for_call + GAS_FOR_RESOLVE_TRANSFER,
+            "{}",
+            ERR_CALL_GAS_TOO_HIGH
+        );
+
+        // The approvals are only refunded once the transfer is resolved, since they have to be
+        // restored if the receiver returns the token.
+        ext_nft_receiver::ext(receiver_id.clone())
+            .with_static_gas(gas_for_call)
+            .nft_on_transfer(sender_id, owner_id.clone(), token_id.clone(), with_redeemed_flag(msg, redeemed))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .nft_resolve_transfer(owner_id, receiver_id, token_id, approved_account_ids),
+            )
+            .into()
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenResolver for Contract {
+    #[private]
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        approved_account_ids: Option<HashMap<AccountId, u64>>,
+    ) -> bool {
+        let transferred = self.tokens.nft_resolve_transfer(
+            previous_owner_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
+            approved_account_ids.clone(),
+        );
+
+        // The return hop is recorded too. The original transfer's deposit only paid for the
+        // forward hop, so the contract pays for this record.
+        if !transferred {
+            self.record_transfer(&token_id, &receiver_id, &previous_owner_id, None);
+        }
+
+        // The standard implementation only refunds the approvals when the receiver asked to
+        // return a token it had already burned. A receiver that keeps the token and burns it
+        // still leaves them to refund here.
+        let kept = matches!(
+            env::promise_result(0),
+            PromiseResult::Successful(value) if serde_json::from_slice::<bool>(&value).is_ok_and(|returned| !returned)
+        );
+        if transferred && (kept || self.tokens.owner_by_id.contains_key(&token_id)) {
+            if let Some(approved_account_ids) = approved_account_ids {
+                refund_approved_account_ids(previous_owner_id, &approved_account_ids);
+            }
+        }
+        transferred
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenApproval for Contract {
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        assert!(env::attached_deposit() >= 1, "{}", ERR_ONE_YOCTO_REQUIRED);
+        let (owner_id, approval_id, storage_used) = self.internal_approve(&token_id, &account_id);
+        refund_deposit(storage_used);
+
+        msg.map(|msg| {
+            ext_nft_approval_receiver::ext(account_id)
+                .with_static_gas(env::prepaid_gas() - GAS_FOR_NFT_APPROVE)
+                .nft_on_approve(token_id, owner_id, approval_id, msg)
+        })
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_UNKNOWN_TOKEN);
+        assert_eq!(env::predecessor_account_id(), owner_id, "{}", ERR_PREDECESSOR_NOT_OWNER);
+
+        let approvals_by_id = self.tokens.approvals_by_id.as_mut().unwrap();
+        let mut approved_account_ids = match approvals_by_id.get(&token_id) {
+            Some(approved_account_ids) => approved_account_ids,
+            None => return,
+        };
+        if approved_account_ids.remove(&account_id).is_none() {
+            return;
+        }
+        if approved_account_ids.is_empty() {
+            approvals_by_id.remove(&token_id);
+        } else {
+            approvals_by_id.insert(&token_id, &approved_account_ids);
+        }
+
+        let refund = Balance::from(bytes_for_approved_account_id(&account_id)) * env::storage_byte_cost();
+        env::log_str(&format!("Revoked {} on token {}, refunding {} yoctoNEAR", account_id, token_id, refund));
+        refund_approved_account_ids_iter(owner_id, std::iter::once(&account_id));
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect(ERR_UNKNOWN_TOKEN);
+        assert_eq!(env::predecessor_account_id(), owner_id, "{}", ERR_PREDECESSOR_NOT_OWNER);
+
+        if let Some(approved_account_ids) = self.tokens.approvals_by_id.as_mut().unwrap().remove(&token_id) {
+            let bytes: u64 = approved_account_ids.keys().map(bytes_for_approved_account_id).sum();
+            env::log_str(&format!(
+                "Revoked {} approvals on token {}, refunding {} yoctoNEAR",
+                approved_account_ids.len(),
+                token_id,
+                Balance::from(bytes) * env::storage_byte_cost()
+            ));
+            refund_approved_account_ids(owner_id, &approved_account_ids);
+        }
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        self.tokens.nft_is_approved(token_id, approved_account_id, approval_id)
+    }
+}
+
+/// Buys a ticket with the payment token. The token contract only calls this once the
+/// `ft_transfer_call` transfer has succeeded, so a failed payment never mints. A panic here
+/// rolls the mint back and the token contract refunds the whole amount, as it does for the
+/// unused amount returned.
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// `msg` is the ticket's receiver, or empty to send it to `sender_id`. Since the call carries
+    /// no NEAR, the sender's `storage_deposit` balance pays for the ticket's storage.
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let (payment_token_id, price) = self.payment_token.clone().expect(ERR_PAYMENT_TOKEN_NOT_SET);
+        assert_eq!(env::predecessor_account_id(), payment_token_id, "{}", ERR_PAYMENT_TOKEN_MISMATCH);
+        if amount.0 < price {
+            env::log_str(&fill(ERR_FT_UNDERPAID, &[&price, &amount.0]));
+            return PromiseOrValue::Value(amount);
+        }
+        let receiver_id = if msg.is_empty() {
+            sender_id.clone()
+        } else {
+            msg.parse().expect(ERR_INVALID_FT_MSG)
+        };
+
+        let initial_storage_usage = env::storage_usage();
+        let attributes = self.prepare_purchase(&sender_id, None, vec![]);
+        let token = self.internal_mint_ticket(receiver_id, attributes);
+        assert_eq!(
+            self.draw_storage_balance(&sender_id, initial_storage_usage),
+            env::storage_usage(),
+            "{}",
+            ERR_STORAGE_BALANCE_TOO_LOW
+        );
+
+        self.ft_revenue += price;
+        NftMint { owner_id: &token.owner_id, token_ids: &[&token.token_id], memo: None }.emit();
+        PromiseOrValue::Value(U128(amount.0 - price))
+    }
+}
+
+/// Lets buyers prepay storage before a drop so `nft_buy` only needs the minting price attached.
+/// Per NEP-145 the balance above the registration minimum is available, both for buying and
+/// for `storage_withdraw`.
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let mut deposit = env::attached_deposit();
+        let balance = self.storage_balances.get(&account_id);
+        let min_balance = self.storage_balance_bounds().min.0;
+        if balance.is_none() {
+            assert!(deposit >= min_balance, "{}", fill(ERR_REGISTRATION_DEPOSIT, &[&min_balance]));
+        }
+        if registration_only == Some(true) {
+            let kept = if balance.is_some() { 0 } else { min_balance };
+            let refund = deposit - kept;
+            if refund > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
+            deposit = kept;
+        }
+        self.storage_balances.insert(&account_id, &(balance.unwrap_or(0) + deposit));
+        self.storage_balance(&account_id).unwrap()
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let balance = self.storage_balances.get(&account_id).expect(ERR_NOT_REGISTERED);
+        let available = balance.saturating_sub(self.storage_balance_bounds().min.0);
+        let amount = amount.map_or(available, |amount| amount.0);
+        assert!(amount <= available, "{}", fill(ERR_STORAGE_WITHDRAW_TOO_HIGH, &[&available]));
+
+        self.storage_balances.insert(&account_id, &(balance - amount));
+        if amount > 0 {
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+        self.storage_balance(&account_id).unwrap()
+    }
+
+    /// Refunds the remaining balance. Tickets already bought are unaffected, so `force` makes no difference.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let _ = force;
+        let account_id = env::predecessor_account_id();
+        match self.storage_balances.remove(&account_id) {
+            Some(balance) => {
+                if balance > 0 {
+                    Promise::new(account_id).transfer(balance);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(env::storage_byte_cost() * Balance::from(MIN_STORAGE_BALANCE_BYTES)),
+            max: None,
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_balance(&account_id)
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenEnumeration for Contract {
+    fn nft_total_supply(&self) -> U128 {
+        self.tokens.nft_total_supply()
+    }
+
+    /// Pages are capped at `MAX_LIMIT` tokens, also when `limit` is omitted.
+    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        self.tokens.nft_tokens(from_index, Some(clamp_limit(limit)))
+    }
+
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        self.tokens.nft_supply_for_owner(account_id)
+    }
+
+    /// Pages are capped at `MAX_LIMIT` tokens, also when `limit` is omitted.
+    fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        self.tokens.nft_tokens_for_owner(account_id, from_index, Some(clamp_limit(limit)))
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenMetadataProvider for Contract {
+    fn nft_metadata(&self) -> NFTContractMetadata {
+        self.metadata.get().unwrap()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const MINT_STORAGE_COST: u128 = 5870000000000000000000;
+    const MINTING_PRICE: u128 = 1000000000000000000000000;
+    const BUY_STORAGE_COST: u128 = 10000000000000000000000;
+    const TRANSFER_STORAGE_COST: u128 = 10000000000000000000000;
+
+    /// Tickets can't be minted to the contract's own account, so tests deploy it to an account
+    /// of its own rather than the owner's.
+    fn contract_account_id() -> AccountId {
+        "tickets.near".parse().unwrap()
+    }
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(contract_account_id())
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    fn sample_token_metadata() -> TokenMetadata {
+        TokenMetadata {
+            title: Some("Olympus Mons".into()),
+            description: Some("The tallest mountain in the charted solar system".into()),
+            media: None,
+            media_hash: None,
+            copies: Some(1u64),
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    fn buy_ticket(context: &mut VMContextBuilder, contract: &mut Contract, buyer: AccountId) -> Token {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(buyer)
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None)
+    }
+
+    #[test]
+    fn test_new() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1));
+        testing_env!(context.is_view(true).build());
+        assert_eq!(contract.nft_token("1".to_string()), None);
+        assert_eq!(contract.owner_id(), accounts(1));
+    }
+
+    #[test]
+    fn test_ticket_template() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1));
+        testing_env!(context.is_view(true).build());
+        let template = contract.ticket_template();
+        assert_eq!(template.title, Some("Ticket to paradise".to_string()));
+        assert_eq!(template.copies, Some(100));
+    }
+
+    #[test]
+    fn test_migrate_from_copies_as_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        let old = OldContract {
+            tokens: contract.tokens,
+            metadata: contract.metadata,
+            token_metadata: contract.token_metadata,
+            minted_tokens: contract.minted_tokens,
+            minting_price: contract.minting_price,
+            perpetual_royalties: contract.perpetual_royalties,
+            allowlist_signer: contract.allowlist_signer,
+            total_revenue: contract.total_revenue,
+            redeemed_count: contract.redeemed_count,
+            max_held_per_account: contract.max_held_per_account,
+            referral_bps: contract.referral_bps,
+            referral_earnings: contract.referral_earnings,
+            tiers: contract.tiers,
+            transfer_fee: contract.transfer_fee,
+            transfer_fee_on_plain_transfers: contract.transfer_fee_on_plain_transfers,
+            max_total_royalty_bps: contract.max_total_royalty_bps,
+            event_info: contract.event_info,
+            storage_balances: contract.storage_balances,
+            redemption_grace_ns: contract.redemption_grace_ns,
+            transfer_cooldown_ns: contract.transfer_cooldown_ns,
+            minted_at: contract.minted_at,
+            buy_requests: contract.buy_requests,
+            metadata_frozen: contract.metadata_frozen,
+            payment_token: contract.payment_token,
+            hard_max_supply: contract.hard_max_supply,
+            promo_codes: contract.promo_codes,
+            transfer_memos: contract.transfer_memos,
+            transfer_history: contract.transfer_history,
+            waitlist: contract.waitlist,
+            admins: contract.admins,
+            proceeds: contract.proceeds,
+            treasury_split: contract.treasury_split,
+            poap_metadata: contract.poap_metadata,
+            poaps_minted: contract.poaps_minted,
+            default_call_gas: contract.default_call_gas,
+            redemptions_locked: contract.redemptions_locked,
+            refund_policy: contract.refund_policy,
+            paid_prices: contract.paid_prices,
+            allowed_marketplaces: contract.allowed_marketplaces,
+            token_id_width: contract.token_id_width,
+            claim_codes: contract.claim_codes,
+            max_approvals_per_token: contract.max_approvals_per_token,
+            burned_tokens: contract.burned_tokens,
+            primary_split: contract.primary_split,
+            max_buys_per_block: contract.max_buys_per_block,
+            buys_in_block: contract.buys_in_block,
+            consignments: contract.consignments,
+            consignment_commission_bps: contract.consignment_commission_bps,
+            max_redemptions: contract.max_redemptions,
+            blocked_accounts: contract.blocked_accounts,
+            minimal_metadata: contract.minimal_metadata,
+            payout_confirmation_required: contract.payout_confirmation_required,
+            pending_payouts: contract.pending_payouts,
+            flagged_payouts: contract.flagged_payouts,
+            mint_end_ns: contract.mint_end_ns,
+            random_traits: contract.random_traits,
+            permit_keys: contract.permit_keys,
+            refunds_in_progress: contract.refunds_in_progress,
+            redeemed_index: contract.redeemed_index,
+            refunds_issued: contract.refunds_issued,
+            lock_transfer_after_redeem: contract.lock_transfer_after_redeem,
+        };
+        env::state_write(&old);
+
+        testing_env!(context.predecessor_account_id(contract_account_id()).build());
+        let contract = Contract::migrate();
+        assert_eq!(contract.max_supply(), 100);
+        assert_eq!(contract.tokens_left(), 99);
+        assert_eq!(contract.nft_token("1".to_string()).unwrap().owner_id, accounts(1));
+        assert_eq!(contract.ft_revenue(), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn test_default() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let _contract = Contract::default();
+    }
+
+    #[test]
+    fn test_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+
+        let token_id = "0".to_string();
+        let token = contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        assert_eq!(token.token_id, token_id);
+        assert_eq!(token.owner_id, accounts(0));
+        assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+        assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        assert_eq!(contract.tokens_left(), 99);
+    }
+
+    #[test]
+    fn test_buy_after_custom_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.nft_mint("backstage".to_string(), accounts(1), sample_token_metadata());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(token.token_id, "2");
+        assert_eq!(contract.nft_total_supply(), U128(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Numeric token ids are reserved for sequential tickets")]
+    fn test_mint_sequential_token_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(MINT_STORAGE_COST).build());
+        contract.nft_mint("0002".to_string(), accounts(1), sample_token_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_mint_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_mint("0".to_string(), accounts(1), sample_token_metadata());
+    }
+
+    #[test]
+    fn test_mint_with_non_json_extra() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        let token_metadata = TokenMetadata { extra: Some("front row".to_string()), ..sample_token_metadata() };
+        contract.nft_mint("backstage".to_string(), accounts(1), token_metadata);
+
+        assert!(contract.token_attributes("backstage".to_string()).is_empty());
+        assert_eq!(contract.nft_tokens_detailed(None, None).len(), 1);
+        testing_env!(context.attached_deposit(1).build());
+        contract.reconcile_minted();
+        assert_eq!(contract.redeemed_count, 0);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        if let Some(token) = contract.nft_token(token_id.clone()) {
+            assert_eq!(token.token_id, token_id);
+            assert_eq!(token.owner_id.to_string(), accounts(1).to_string());
+            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
+            assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
+        } else {
+            panic!("token not correctly created, or not found by nft_token");
+        }
+    }
+
+    #[test]
+    fn test_approve() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+    }
+
+    #[test]
+    fn test_revoke() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        // alice revokes bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_revoke(token_id.clone(), accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+    }
+
+    #[test]
+    fn test_revoke_all() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        // alice approves bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        // alice revokes bob
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(0))
+            .build());
+        contract.nft_revoke_all(token_id.clone());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(env::account_balance())
+            .is_view(true)
+            .attached_deposit(0)
+            .build());
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+    }
+
+    #[test]
+    fn test_redeem_before_expiry() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.expires_at = Some("1000".to_string());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1000).attached_deposit(1).build());
+        assert!(!contract.is_expired(token.token_id.clone()));
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Ticket expired")]
+    fn test_redeem_expired() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.expires_at = Some("1000".to_string());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1001).attached_deposit(1).build());
+        assert!(contract.is_expired(token.token_id.clone()));
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Ticket not yet valid")]
+    fn test_redeem_before_start() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.starts_at = Some("1000".to_string());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(999).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    fn test_redeem_at_start() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.starts_at = Some("1000".to_string());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1000).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    fn test_redeem_with_proof() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1000).attached_deposit(1).build());
+        let proof = contract.redeem_with_proof(token.token_id.clone());
+        assert_eq!(
+            proof,
+            RedemptionProof {
+                contract_id: env::current_account_id(),
+                token_id: token.token_id,
+                redeemer_id: accounts(1),
+                redeemed_at_ns: 1000,
+            }
+        );
+        assert_eq!(
+            get_logs().last().unwrap(),
+            &format!(
+                "EVENT_JSON:{}",
+                json!({
+                    "standard": TICKET_EVENT_STANDARD,
+                    "version": TICKET_EVENT_VERSION,
+                    "event": "nft_redemption_proof",
+                    "data": [proof],
+                })
+            )
+        );
+    }
+
+    fn contract_with_max_buys_per_block(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_max_buys_per_block(Some(1));
+        assert_eq!(contract.max_buys_per_block(), Some(1));
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Too many buys from this account in one block")]
+    fn test_buys_in_same_block_above_limit() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_max_buys_per_block(&mut context);
+
+        testing_env!(context.block_index(7).build());
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+    }
+
+    #[test]
+    fn test_buys_in_next_block_within_limit() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_max_buys_per_block(&mut context);
+
+        testing_env!(context.block_index(7).build());
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(2));
+        testing_env!(context.block_index(8).build());
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+    }
+
+    #[test]
+    fn test_buy_with_extra_attributes() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token = contract.nft_buy(None, Some(vec![("name".to_string(), "Alice".to_string())]), None, None, None, None, None, None);
+        assert_eq!(
+            token.metadata.unwrap().extra,
+            Some(json!({"attributes": [
+                {"trait_type": "redeemed", "value": "false"},
+                {"trait_type": "name", "value": "Alice"}
+            ]}).to_string())
+        );
+
+        testing_env!(context.attached_deposit(1).build());
+        let token = contract.redeem_nft(token.token_id);
+        let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+        assert_eq!(get_attribute(&attributes, "redeemed"), Some("true"));
+        assert_eq!(get_attribute(&attributes, "name"), Some("Alice"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The redeemed attribute is reserved")]
+    fn test_buy_with_reserved_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(None, Some(vec![("redeemed".to_string(), "true".to_string())]), None, None, None, None, None, None);
+    }
+
+    #[test]
+    fn test_buy_gift_with_message() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let message = "Happy birthday!".to_string();
+        let gift = contract.nft_buy(Some(accounts(2)), None, None, None, None, None, None, Some(message.clone()));
+        assert_eq!(gift.owner_id, accounts(2));
+        let attributes = contract.token_attributes(gift.token_id);
+        assert_eq!(get_attribute(&attributes, GIFT_MESSAGE_TRAIT), Some(message.as_str()));
+        assert!(get_logs().last().unwrap().contains(r#""memo":"Happy birthday!""#));
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let own = contract.nft_buy(None, None, None, None, None, None, None, Some(message));
+        assert_eq!(get_attribute(&contract.token_attributes(own.token_id), GIFT_MESSAGE_TRAIT), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Gift messages can be at most 280 characters")]
+    fn test_buy_gift_with_long_message() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let message = "x".repeat(MAX_GIFT_MESSAGE_LEN + 1);
+        contract.nft_buy(Some(accounts(2)), None, None, None, None, None, None, Some(message));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: At most 8 extra attributes are allowed")]
+    fn test_buy_with_too_many_extra_attributes() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let extra_attributes = (0..=MAX_EXTRA_ATTRIBUTES).map(|i| (format!("seat_{}", i), "A1".to_string())).collect();
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Attribute names and values can be at most 64 characters")]
+    fn test_buy_with_long_extra_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let extra_attributes = vec![("seat".to_string(), "x".repeat(MAX_EXTRA_ATTRIBUTE_LEN + 1))];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The gift_message attribute is reserved")]
+    fn test_buy_with_gift_message_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let extra_attributes = vec![(GIFT_MESSAGE_TRAIT.to_string(), "From me".to_string())];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    fn allowlist_keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+        let public = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn allowlist_signature(keypair: &Keypair, receiver_id: &AccountId, nonce: u64, expiry_ns: u64) -> Vec<u8> {
+        let message = format!("{}:{}:{}:{}", receiver_id, nonce, expiry_ns, contract_account_id());
+        keypair.sign(message.as_bytes()).to_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_buy_signed() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let keypair = allowlist_keypair(1);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(keypair.public.to_bytes().to_vec());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100)
+            .build());
+        let signature = allowlist_signature(&keypair, &accounts(1), 1, 100);
+        let token = contract.nft_buy_signed(accounts(1), signature, 1, 100);
+        assert_eq!(token.owner_id, accounts(1));
+        assert_eq!(contract.allowlist_nonce(accounts(1)), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Allowlist nonce already used")]
+    fn test_buy_signed_replayed() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let keypair = allowlist_keypair(1);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(keypair.public.to_bytes().to_vec());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(100)
+            .build());
+        let signature = allowlist_signature(&keypair, &accounts(1), 1, 100);
+        contract.nft_buy_signed(accounts(1), signature.clone(), 1, 100);
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy_signed(accounts(1), signature, 1, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Invalid signature")]
+    fn test_buy_signed_for_other_receiver() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let keypair = allowlist_keypair(1);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(keypair.public.to_bytes().to_vec());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let signature = allowlist_signature(&keypair, &accounts(1), 1, 100);
+        contract.nft_buy_signed(accounts(2), signature, 1, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Signature expired")]
+    fn test_buy_signed_expired() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let keypair = allowlist_keypair(1);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(keypair.public.to_bytes().to_vec());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(101)
+            .build());
+        let signature = allowlist_signature(&keypair, &accounts(1), 1, 100);
+        contract.nft_buy_signed(accounts(1), signature, 1, 100);
+    }
+
+    #[test]
+    fn test_total_revenue() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(contract.total_revenue(), U128(0));
+
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(2));
+        assert_eq!(contract.total_revenue(), U128(2 * MINTING_PRICE));
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover the minting price and storage")]
+    fn test_buy_without_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+    }
+
+    fn sample_event_info() -> EventInfo {
+        EventInfo {
+            venue: "Olympiastadion".to_string(),
+            event_date_ns: 1_700_000_000_000_000_000,
+            doors_open_ns: Some(1_699_996_400_000_000_000),
+            city: Some("Berlin".to_string()),
+        }
+    }
+
+    fn transferred_to(account_id: &AccountId) -> Option<Balance> {
+        get_created_receipts()
+            .into_iter()
+            .filter(|receipt| &receipt.receiver_id == account_id)
+            .flat_map(|receipt| receipt.actions)
+            .find_map(|action| match action {
+                VmAction::Transfer { deposit } => Some(deposit),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_transfer_refunds_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), token_id.clone(), Some(1), None);
+
+        assert_eq!(
+            transferred_to(&accounts(0)),
+            Some(Balance::from(bytes_for_approved_account_id(&accounts(1))) * env::storage_byte_cost())
+        );
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+    }
+
+    #[test]
+    fn test_resolve_transfer_refunds_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.nft_transfer_call(accounts(2), token_id.clone(), None, None, "".to_string());
+
+        // The receiver keeps the token, so the approvals the sender had are refunded.
+        let mut approved_account_ids = HashMap::new();
+        approved_account_ids.insert(accounts(1), 1);
+        testing_env!(
+            context.attached_deposit(0).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"false".to_vec())]
+        );
+        assert!(contract.nft_resolve_transfer(
+            accounts(0),
+            accounts(2),
+            token_id.clone(),
+            Some(approved_account_ids)
+        ));
+        assert_eq!(
+            transferred_to(&accounts(0)),
+            Some(Balance::from(bytes_for_approved_account_id(&accounts(1))) * env::storage_byte_cost())
+        );
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+    }
+
+    #[test]
+    fn test_resolve_transfer_refunds_approvals_of_burned_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(3)]);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.nft_transfer_call(accounts(2), token.token_id.clone(), None, None, "".to_string());
+
+        // The receiver burns the ticket in nft_on_transfer and returns `false`, keeping it.
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(1)
+            .build());
+        contract.nft_burn(token.token_id.clone());
+        let mut approved_account_ids = HashMap::new();
+        approved_account_ids.insert(accounts(3), 0);
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).attached_deposit(0).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"false".to_vec())]
+        );
+        assert!(contract.nft_resolve_transfer(
+            accounts(1),
+            accounts(2),
+            token.token_id,
+            Some(approved_account_ids)
+        ));
+        assert_eq!(
+            transferred_to(&accounts(1)),
+            Some(Balance::from(bytes_for_approved_account_id(&accounts(3))) * env::storage_byte_cost())
+        );
+    }
+
+    #[test]
+    fn test_effective_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(3), 1000);
+        royalties.insert(accounts(1), 500);
+        contract.perpetual_royalties = Some(royalties);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        // accounts(1) owns the token, so its royalty is folded into the owner's residual.
+        let split = contract.effective_royalties(token.token_id);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[&accounts(3)], 1000);
+        assert_eq!(split[&accounts(1)], 9000);
+    }
+
+    #[test]
+    fn test_reapprove_without_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST)
+            .predecessor_account_id(accounts(0))
+            .build());
+        let token_id = "0".to_string();
+        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+
+        // Approving the same account again only needs the security deposit.
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(2)));
+
+        // Any storage deposit attached to a re-approval is refunded in full.
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(150000000000000000000)
+            .build());
+        contract.nft_approve(token_id.clone(), accounts(1), None);
+        assert_eq!(transferred_to(&accounts(0)), Some(150000000000000000000));
+    }
+
+    #[test]
+    fn test_redeem_many() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let first = buy_ticket(&mut context, &mut contract, accounts(1));
+        let second = buy_ticket(&mut context, &mut contract, accounts(1));
+        let other = buy_ticket(&mut context, &mut contract, accounts(2));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(first.token_id.clone());
+        let redeemed = contract.redeem_many(
+            vec![first.token_id, second.token_id.clone(), other.token_id],
+            false,
+        );
+        assert_eq!(redeemed.len(), 1);
+        assert_eq!(redeemed[0].token_id, second.token_id);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            &format!(
+                r#"EVENT_JSON:{{"data":[{{"owner_id":"{}","token_ids":["{}"]}}],"event":"nft_redeem","standard":"ticket","version":"1.0.0"}}"#,
+                accounts(1),
+                second.token_id
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token already redeemed")]
+    fn test_redeem_many_stop_on_error() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let first = buy_ticket(&mut context, &mut contract, accounts(1));
+        let second = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(first.token_id.clone());
+        contract.redeem_many(vec![second.token_id, first.token_id], true);
+    }
+
+    #[test]
+    fn test_unredeem() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id.clone());
+        assert_eq!(contract.redeemed_count(), 1);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let token = contract.unredeem_nft(token.token_id);
+        let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("false"));
+        assert_eq!(contract.redeemed_count(), 0);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id.clone());
+        assert_eq!(contract.redeemed_count(), 1);
+        assert_eq!(contract.redemptions_remaining(token.token_id), 0);
+    }
+
+    fn contract_with_multi_entry_ticket(context: &mut VMContextBuilder) -> (Contract, Token) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_max_redemptions(3);
+        let token = buy_ticket(context, &mut contract, accounts(1));
+        (contract, token)
+    }
+
+    #[test]
+    fn test_multi_entry_redemptions() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_multi_entry_ticket(&mut context);
+        assert_eq!(contract.redemptions_remaining(token.token_id.clone()), 3);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id.clone());
+        let token = contract.redeem_nft(token.token_id);
+        let attributes = extra_to_attributes(&token.metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, REDEMPTIONS_USED_TRAIT), Some("2"));
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("false"));
+        assert_eq!(contract.redemptions_remaining(token.token_id.clone()), 1);
+        assert_eq!(contract.redeemed_count(), 0);
+
+        let token = contract.redeem_nft(token.token_id);
+        let attributes = extra_to_attributes(&token.metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("true"));
+        assert_eq!(contract.redemptions_remaining(token.token_id.clone()), 0);
+        assert_eq!(contract.redeemed_count(), 1);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.unredeem_nft(token.token_id.clone());
+        assert_eq!(contract.redemptions_remaining(token.token_id), 1);
+        assert_eq!(contract.redeemed_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token already redeemed")]
+    fn test_multi_entry_redemptions_used_up() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_multi_entry_ticket(&mut context);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        for _ in 0..4 {
+            contract.redeem_nft(token.token_id.clone());
+        }
+    }
+
+    #[test]
+    fn test_redeemed_index_matches_metadata() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_multi_entry_ticket(&mut context);
+        let token_id = token.token_id;
+        let assert_in_sync = |contract: &Contract, expected: bool| {
+            let token = contract.nft_token(token_id.clone()).unwrap();
+            let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+            assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT) == Some("true"), expected);
+            assert_eq!(contract.is_redeemed(token_id.clone()), expected);
+        };
+        assert_in_sync(&contract, false);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token_id.clone());
+        contract.redeem_nft(token_id.clone());
+        assert_in_sync(&contract, false);
+        contract.redeem_nft(token_id.clone());
+        assert_in_sync(&contract, true);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.unredeem_nft(token_id.clone());
+        assert_in_sync(&contract, false);
+        contract.unredeem_nft(token_id.clone());
+        assert_in_sync(&contract, false);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token_id.clone());
+        contract.redeem_nft(token_id.clone());
+        assert_in_sync(&contract, true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Must attach at least the 1000000000000000000000000 yoctoNEAR price")]
+    fn test_buy_with_deposit_below_price() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(MINTING_PRICE - 1).build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attach at least 30000000000000 gas")]
+    fn test_buy_with_too_little_gas() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .prepaid_gas(Gas(contract.min_buy_gas().0 - 1))
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The comp attribute is reserved")]
+    fn test_buy_with_comp_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let extra_attributes = vec![(COMP_TRAIT.to_string(), "true".to_string())];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The airdrop attribute is reserved")]
+    fn test_buy_with_airdrop_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let extra_attributes = vec![(AIRDROP_TRAIT.to_string(), "true".to_string())];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The claim_code attribute is reserved")]
+    fn test_buy_with_claim_code_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let extra_attributes = vec![(CLAIM_CODE_TRAIT.to_string(), "true".to_string())];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The max_redemptions attribute is reserved")]
+    fn test_buy_with_redemption_count_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let extra_attributes = vec![(MAX_REDEMPTIONS_TRAIT.to_string(), "100".to_string())];
+        contract.nft_buy(None, Some(extra_attributes), None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_unredeem_by_holder() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id.clone());
+        contract.unredeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Recipient holds too many tickets")]
+    fn test_transfer_above_max_held() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_max_held_per_account(Some(1));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        let token = buy_ticket(&mut context, &mut contract, accounts(2));
+        assert!(!contract.can_receive(accounts(1)));
+        assert!(contract.can_receive(accounts(3)));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.nft_transfer(accounts(1), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_set_token_template() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_token_template(None, None, Some("https://example.com/final.png".to_string()), None, None);
+
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.media, Some("https://example.com/final.png".to_string()));
+        assert_eq!(metadata.title, Some("Ticket to paradise".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Tickets have already been minted")]
+    fn test_set_token_template_after_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(0)).build());
+        contract.set_token_template(Some("Changed".to_string()), None, None, None, None);
+    }
+
+    #[test]
+    fn test_owners_in_range() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(2));
+        buy_ticket(&mut context, &mut contract, accounts(3));
+
+        assert_eq!(
+            contract.owners_in_range(2, 5),
+            vec![("2".to_string(), accounts(2)), ("3".to_string(), accounts(3))]
+        );
+    }
+
+    #[test]
+    fn test_buy_with_referrer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_bps(Some(500));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(None, None, Some(accounts(3)), None, None, None, None, None);
+
+        assert_eq!(transferred_to(&accounts(3)), Some(MINTING_PRICE / 20));
+        assert_eq!(contract.referral_earnings(accounts(3)), U128(MINTING_PRICE / 20));
+        assert_eq!(contract.referral_earnings(accounts(2)), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Self-referrals are not allowed")]
+    fn test_buy_with_self_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_bps(Some(500));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(None, None, Some(accounts(1)), None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Self-referrals are not allowed")]
+    fn test_buy_gift_with_self_referral() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_bps(Some(500));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(Some(accounts(2)), None, Some(accounts(1)), None, None, None, None, None);
+    }
+
+    #[test]
+    fn test_referral_earnings_storage_is_charged() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_bps(Some(500));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+        let refund_without_referrer = transferred_to(&accounts(1)).unwrap();
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy(None, None, Some(accounts(3)), None, None, None, None, None);
+        assert!(transferred_to(&accounts(1)).unwrap() < refund_without_referrer);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Account bob is blocked")]
+    fn test_buy_by_blocked_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.block_account(accounts(1));
+        assert!(contract.is_blocked(accounts(1)));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+    }
+
+    #[test]
+    fn test_buy_after_unblock() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.block_account(accounts(1));
+        contract.unblock_account(accounts(1));
+        assert!(!contract.is_blocked(accounts(1)));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(token.owner_id, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Account bob is blocked")]
+    fn test_approved_transfer_from_blocked_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(2)]);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.block_account(accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.nft_transfer(accounts(3), token.token_id, Some(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Account bob is blocked")]
+    fn test_approve_from_blocked_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.block_account(accounts(1));
+
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Account charlie is blocked")]
+    fn test_transfer_to_blocked_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.block_account(accounts(2));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_buy_before_mint_end() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(999).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_mint_end_ns(Some(1000));
+        assert_eq!(contract.mint_end_ns(), Some(1000));
+        assert_eq!(contract.purchasability(), (true, None));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_buy_after_mint_end() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1000).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_mint_end_ns(Some(1000));
+        assert_eq!(contract.purchasability(), (false, Some("minting_closed".to_string())));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting closed")]
+    fn test_reserve_mint_after_mint_end() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1000).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_mint_end_ns(Some(1000));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.reserve_mint(1, accounts(1));
+    }
+
+    #[test]
+    fn test_random_traits_follow_weights() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let weights = vec![("common".to_string(), 3), ("rare".to_string(), 1), ("unused".to_string(), 0)];
+        contract.set_random_traits(Some(weights.clone()));
+        assert_eq!(contract.random_traits(), Some(weights));
+
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for _ in 0..100 {
+            let token = buy_ticket(&mut context, &mut contract, accounts(1));
+            let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+            *counts.entry(get_attribute(&attributes, RANDOM_TRAIT).unwrap().to_string()).or_default() += 1;
+        }
+        assert_eq!(counts.get("unused"), None);
+        assert_eq!(counts["common"] + counts["rare"], 100);
+        assert!((60..=90).contains(&counts["common"]), "{:?}", counts);
+    }
+
+    #[test]
+    fn test_pick_weighted_trait() {
+        let weights = vec![("common".to_string(), 3), ("unused".to_string(), 0), ("rare".to_string(), 1)];
+        let picks: Vec<&str> = (0..8).map(|roll| pick_weighted_trait(&weights, roll)).collect();
+        assert_eq!(picks, ["common", "common", "common", "rare", "common", "common", "common", "rare"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Tickets have already been minted")]
+    fn test_set_random_traits_after_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_random_traits(Some(vec![("common".to_string(), 1)]));
+    }
+
+    #[test]
+    fn test_tiers() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(2 * MINTING_PRICE), 1);
+        contract.add_tier("GA".to_string(), U128(MINTING_PRICE), 10);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let token = contract.nft_buy_tier("VIP".to_string(), None);
+        let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+        assert_eq!(get_attribute(&attributes, TIER_TRAIT), Some("VIP"));
+
+        assert_eq!(
+            contract.tier_info("VIP".to_string()),
+            TierView {
+                name: "VIP".to_string(),
+                price: U128(2 * MINTING_PRICE),
+                max_supply: 1,
+                minted: 1,
+                remaining: 0,
+                sold_out: true,
+                redeemed: 0,
+            }
+        );
+        assert_eq!(contract.all_tiers().len(), 2);
+        assert_eq!(contract.total_revenue(), U128(2 * MINTING_PRICE));
+    }
+
+    #[test]
+    fn test_tier_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 10);
+        contract.add_tier("GA".to_string(), U128(MINTING_PRICE), 10);
+        let vip_metadata = TokenMetadata {
+            title: Some("VIP pass".to_string()),
+            media: Some("https://example.com/vip.png".to_string()),
+            copies: Some(10),
+            ..sample_token_metadata()
+        };
+        contract.set_tier_metadata("VIP".to_string(), vip_metadata.clone());
+        assert_eq!(contract.tier_metadata("VIP".to_string()), Some(vip_metadata));
+        assert_eq!(contract.tier_metadata("GA".to_string()), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let vip = contract.nft_buy_tier("VIP".to_string(), None).metadata.unwrap();
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let general = contract.nft_buy_tier("GA".to_string(), None).metadata.unwrap();
+
+        assert_eq!(vip.media, Some("https://example.com/vip.png".to_string()));
+        assert_eq!(vip.title, Some("VIP pass".to_string()));
+        assert_eq!(vip.copies, Some(10));
+        assert_eq!(get_attribute(&extra_to_attributes(&vip.extra), TIER_TRAIT), Some("VIP"));
+        assert_eq!(general.media, contract.token_metadata.media);
+        assert_ne!(vip.media, general.media);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The tier already has sales")]
+    fn test_set_tier_metadata_after_sale() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 10);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy_tier("VIP".to_string(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_tier_metadata("VIP".to_string(), sample_token_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Tier sold out")]
+    fn test_buy_sold_out_tier() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 1);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy_tier("VIP".to_string(), None);
+        contract.nft_buy_tier("VIP".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "copies must be a positive number")]
+    fn test_new_without_copies() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut token_metadata = sample_token_metadata();
+        token_metadata.copies = None;
+        Contract::new(
+            accounts(0),
+            NFTContractMetadata {
+                spec: NFT_METADATA_SPEC.to_string(),
+                name: "Example NEAR non-fungible token".to_string(),
+                symbol: "EXAMPLE".to_string(),
+                icon: None,
+                base_uri: None,
+                reference: None,
+                reference_hash: None,
+            },
+            token_metadata,
+            U128(MINTING_PRICE),
+            None,
+            sample_event_info(),
+            1000,
+            false,
+        );
+    }
+
+    #[test]
+    fn test_reserve_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * BUY_STORAGE_COST)
+            .build());
+        let tokens = contract.reserve_mint(3, accounts(2));
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(contract.tokens_left(), 97);
+        assert_eq!(contract.total_revenue(), U128(0));
+        let attributes = extra_to_attributes(&tokens[0].metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, COMP_TRAIT), Some("true"));
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("false"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_reserve_mint_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.reserve_mint(1, accounts(1));
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_fee(Some(U128(1000)), false);
+        assert_eq!(contract.transfer_fee(), Some(U128(1000)));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        // Plain transfers aren't charged unless the organizer opts in.
+        testing_env!(context.attached_deposit(TRANSFER_STORAGE_COST).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+
+        testing_env!(context.attached_deposit(1000 + TRANSFER_STORAGE_COST).predecessor_account_id(accounts(2)).build());
+        contract.nft_transfer_payout(accounts(3), token.token_id, 0, None, U128(MINTING_PRICE), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Must attach the 1000 yoctoNEAR transfer fee plus 1 yoctoNEAR")]
+    fn test_transfer_payout_without_fee() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_fee(Some(U128(1000)), true);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.attached_deposit(1000).predecessor_account_id(accounts(1)).build());
+        contract.nft_transfer_payout(accounts(3), token.token_id, 0, None, U128(MINTING_PRICE), 10);
+    }
+
+    fn contract_with_royalties(context: &mut VMContextBuilder) -> (Contract, Token) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), 1000);
+        royalties.insert(accounts(3), 500);
+        royalties.insert(accounts(4), 200);
+        contract.perpetual_royalties = Some(royalties);
+        let token = buy_ticket(context, &mut contract, accounts(1));
+        (contract, token)
+    }
+
+    #[test]
+    fn test_royalties_not_paid_on_primary_sale() {
+        let mut context = get_context(accounts(0));
+        contract_with_royalties(&mut context);
+
+        for royalty_recipient in [accounts(2), accounts(3), accounts(4)] {
+            assert_eq!(transferred_to(&royalty_recipient), None);
+        }
+    }
+
+    #[test]
+    fn test_primary_split() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let mut primary_split = HashMap::new();
+        primary_split.insert(accounts(2), 1000);
+        primary_split.insert(accounts(3), 2500);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_primary_split(Some(primary_split.clone()));
+        assert_eq!(contract.primary_split(), Some(primary_split));
+
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(transferred_to(&accounts(2)), Some(MINTING_PRICE / 10));
+        assert_eq!(transferred_to(&accounts(3)), Some(MINTING_PRICE / 4));
+        assert_eq!(contract.proceeds(), U128(MINTING_PRICE * 65 / 100));
+        assert_eq!(contract.total_revenue(), U128(MINTING_PRICE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Primary split and referral share can't exceed 10000 basis points")]
+    fn test_primary_split_above_price_with_referrals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_referral_bps(Some(1000));
+
+        let mut primary_split = HashMap::new();
+        primary_split.insert(accounts(2), 9500);
+        contract.set_primary_split(Some(primary_split));
+    }
+
+    #[test]
+    fn test_payout_with_room_for_all_royalties() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+
+        let payout = contract.nft_payout(token.token_id, U128(10000), 4).payout;
+        assert_eq!(payout.len(), 4);
+        assert_eq!(payout[&accounts(2)], U128(1000));
+        assert_eq!(payout[&accounts(3)], U128(500));
+        assert_eq!(payout[&accounts(4)], U128(200));
+        assert_eq!(payout[&accounts(1)], U128(8300));
+    }
+
+    #[test]
+    fn test_payout_max_len_equal_to_royalty_count() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+
+        // The owner takes one of the 3 slots, so the smallest royalty goes to the owner.
+        let payout = contract.nft_payout(token.token_id, U128(10000), 3).payout;
+        assert_eq!(payout.len(), 3);
+        assert_eq!(payout[&accounts(2)], U128(1000));
+        assert_eq!(payout[&accounts(3)], U128(500));
+        assert_eq!(payout[&accounts(1)], U128(8500));
+    }
+
+    #[test]
+    fn test_payout_max_len_below_royalty_count() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+
+        let payout = contract.nft_payout(token.token_id, U128(10000), 2).payout;
+        assert_eq!(payout.len(), 2);
+        assert_eq!(payout[&accounts(2)], U128(1000));
+        assert_eq!(payout[&accounts(1)], U128(9000));
+    }
+
+    #[test]
+    fn test_nft_is_redeemable() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.starts_at = Some("1000".to_string());
+        contract.token_metadata.expires_at = Some("2000".to_string());
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        assert_eq!(contract.nft_is_redeemable("42".to_string()), (false, Some("not_found".to_string())));
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id.clone()),
+            (false, Some("not_yet_valid".to_string()))
+        );
+        testing_env!(context.block_timestamp(2001).build());
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id.clone()),
+            (false, Some("expired".to_string()))
+        );
+
+        testing_env!(context.block_timestamp(1500).attached_deposit(1).build());
+        assert_eq!(contract.nft_is_redeemable(token.token_id.clone()), (true, None));
+        contract.redeem_nft(token.token_id.clone());
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id),
+            (false, Some("already_redeemed".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_airdrop() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * BUY_STORAGE_COST)
+            .build());
+        let tokens = contract.airdrop(vec![accounts(1), accounts(2), accounts(3)]);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[1].owner_id, accounts(2));
+        assert_eq!(contract.tokens_left(), 97);
+        let attributes = extra_to_attributes(&tokens[0].metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, AIRDROP_TRAIT), Some("true"));
+    }
+
+    fn contract_with_burned_ticket(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        for _ in 0..3 {
+            buy_ticket(context, &mut contract, accounts(1));
+        }
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.nft_burn("3".to_string());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(BUY_STORAGE_COST)
+            .build());
+        contract
+    }
+
+    #[test]
+    fn test_mint_specific() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_burned_ticket(&mut context);
+        assert_eq!(contract.total_burned(), 1);
+
+        let token = contract.mint_specific("3".to_string(), accounts(2));
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(contract.nft_token("3".to_string()).unwrap().owner_id, accounts(2));
+        assert!(!contract.is_redeemed("3".to_string()));
+        assert_eq!(contract.total_burned(), 0);
+        assert_eq!(contract.nft_total_supply(), U128(3));
+
+        let next = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(next.token_id, "4");
+    }
+
+    #[test]
+    #[should_panic(expected = "Token id already exists")]
+    fn test_mint_specific_existing_id() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_burned_ticket(&mut context);
+        contract.mint_specific("2".to_string(), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Only ids freed by a burn can be reissued")]
+    fn test_mint_specific_unissued_id() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_burned_ticket(&mut context);
+        contract.mint_specific("4".to_string(), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Only ids freed by a burn can be reissued")]
+    fn test_mint_specific_id_skipped_by_custom_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.nft_mint("backstage".to_string(), accounts(1), sample_token_metadata());
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(BUY_STORAGE_COST).build());
+        contract.mint_specific("1".to_string(), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The burned token count is out of sync, run reconcile_minted")]
+    fn test_mint_specific_with_burned_count_out_of_sync() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_burned_ticket(&mut context);
+        contract.burned_tokens = 0;
+        contract.mint_specific("3".to_string(), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Not enough tickets left")]
+    fn test_airdrop_above_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 2;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(3 * BUY_STORAGE_COST)
+            .build());
+        contract.airdrop(vec![accounts(1), accounts(2), accounts(3)]);
+    }
+
+    #[test]
+    fn test_buy_for_implicit_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let implicit_id: AccountId = "0f".repeat(32).parse().unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let token = contract.nft_buy(Some(implicit_id.clone()), None, None, None, None, None, None, None);
+        assert_eq!(token.owner_id, implicit_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "implicit account ids are 64 hex characters")]
+    fn test_buy_for_mistyped_implicit_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let mistyped_id: AccountId = format!("{}0g", "0f".repeat(31)).parse().unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(Some(mistyped_id), None, None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "implicit account ids are 64 hex characters")]
+    fn test_buy_for_truncated_implicit_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let truncated_id: AccountId = "0f".repeat(32)[1..].parse().unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(Some(truncated_id), None, None, None, None, None, None, None);
+    }
+
+    #[test]
+    fn test_buy_for_overlong_implicit_account() {
+        // 65 characters is past the account id length limit, so the call arguments are
+        // rejected before nft_buy runs.
+        let overlong_id = format!("\"{}0\"", "0f".repeat(32));
+        assert!(serde_json::from_str::<AccountId>(&overlong_id).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot mint to contract account")]
+    fn test_buy_for_contract_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(Some(contract_account_id()), None, None, None, None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot mint to contract account")]
+    fn test_airdrop_to_contract_account() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(2 * BUY_STORAGE_COST).build());
+        contract.airdrop(vec![accounts(1), contract_account_id()]);
+    }
+
+    fn contract_with_claim_code(context: &mut VMContextBuilder, code: &str) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_claim_codes(vec![hash_claim_code(code)]);
+        contract
+    }
+
+    #[test]
+    fn test_claim_by_code() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_claim_code(&mut context, "SCRATCH-1234");
+        assert_eq!(contract.claim_code_status(hash_claim_code("SCRATCH-1234")), Some(false));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(BUY_STORAGE_COST)
+            .build());
+        let token = contract.claim_by_code("SCRATCH-1234".to_string(), accounts(2));
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(contract.tokens_left(), 99);
+        let attributes = extra_to_attributes(&token.metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, CLAIM_CODE_TRAIT), Some("true"));
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("false"));
+        assert_eq!(contract.claim_code_status(hash_claim_code("SCRATCH-1234")), Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "Code already claimed")]
+    fn test_claim_by_code_twice() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_claim_code(&mut context, "SCRATCH-1234");
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .storage_usage(env::storage_usage())
+            .attached_deposit(BUY_STORAGE_COST)
+            .build());
+        contract.claim_by_code("SCRATCH-1234".to_string(), accounts(1));
+        contract.claim_by_code("SCRATCH-1234".to_string(), accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Unknown claim code")]
+    fn test_claim_by_unknown_code() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_claim_code(&mut context, "SCRATCH-1234");
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(BUY_STORAGE_COST).build());
+        contract.claim_by_code("SCRATCH-9999".to_string(), accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Claim codes must be lowercase hex sha256 hashes")]
+    fn test_add_plaintext_claim_code() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_claim_codes(vec!["SCRATCH-1234".to_string()]);
+    }
+
+    #[test]
+    fn test_nft_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.nft_burn(token.token_id.clone());
+
+        assert!(contract.nft_token(token.token_id.clone()).is_none());
+        let owned = contract.nft_tokens_for_owner(accounts(1), None, None);
+        assert_eq!(owned.len(), 1);
+        assert_ne!(owned[0].token_id, token.token_id);
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.tokens_left(), 98);
+        assert!(transferred_to(&accounts(1)).is_some_and(|refund| refund > 0));
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"nft_burn\"")));
+    }
+
+    #[test]
+    fn test_reconcile_minted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let tokens: Vec<Token> = (0..3).map(|_| buy_ticket(&mut context, &mut contract, accounts(1))).collect();
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(tokens[0].token_id.clone());
+        contract.nft_burn(tokens[1].token_id.clone());
+
+        contract.minted_tokens = 1;
+        contract.burned_tokens = 0;
+        contract.redeemed_count = 5;
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.reconcile_minted();
+        assert_eq!(contract.minted_tokens, 3);
+        assert_eq!(contract.total_burned(), 1);
+        assert_eq!(contract.redeemed_count(), 1);
+        let logs = get_logs();
+        assert_eq!(logs.iter().filter(|log| log.contains("counters_reconciled")).count(), 3);
+
+        // Counters that are already right are left alone.
+        testing_env!(context.build());
+        contract.reconcile_minted();
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_minted_after_poap_burn() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_poap_metadata(Some(sample_poap_metadata()));
+        let tokens: Vec<Token> = (0..2).map(|_| buy_ticket(&mut context, &mut contract, accounts(1))).collect();
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.redeem_nft(tokens[0].token_id.clone());
+        contract.nft_burn(format!("poap-{}", tokens[0].token_id));
+        contract.nft_burn(tokens[1].token_id.clone());
+        assert_eq!(contract.total_burned(), 2);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.reconcile_minted();
+        assert!(get_logs().is_empty());
+        assert_eq!(contract.minted_tokens, 2);
+        assert_eq!(contract.total_burned(), 2);
+    }
+
+    #[test]
+    fn test_reconcile_minted_skips_ids_outside_sequence() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.tokens.internal_mint("99999".to_string(), accounts(1), Some(sample_token_metadata()));
+        contract.minted_tokens += 1;
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.reconcile_minted();
+        assert!(get_logs().is_empty());
+        assert_eq!(contract.minted_tokens, 2);
+        assert!(contract.supply_invariant_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_reconcile_minted_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.reconcile_minted();
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token not owned by the caller")]
+    fn test_nft_burn_not_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+        contract.nft_burn(token.token_id);
+    }
+
+    #[test]
+    fn test_set_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(contract.max_total_royalty_bps(), 5000);
+
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), 3000);
+        royalties.insert(accounts(3), 2000);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_royalties(Some(royalties));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        let payout = contract.nft_payout(token.token_id, U128(10000), 3).payout;
+        assert_eq!(payout[&accounts(1)], U128(5000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Royalties add up to 5001 basis points, above the 5000 cap")]
+    fn test_set_royalties_above_cap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), 3000);
+        royalties.insert(accounts(3), 2001);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_royalties(Some(royalties));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Royalties add up to 1700 basis points, above the 1000 cap")]
+    fn test_lower_royalty_cap_below_configured_royalties() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, _) = contract_with_royalties(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_max_total_royalty_bps(1000);
+    }
+
+    #[test]
+    fn test_token_attributes() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let token = contract.nft_buy(None, Some(vec![("seat".to_string(), "A12".to_string())]), None, None, None, None, None, None);
+        assert_eq!(
+            contract.token_attributes(token.token_id),
+            vec![
+                (REDEEMED_TRAIT.to_string(), "false".to_string()),
+                ("seat".to_string(), "A12".to_string()),
+            ]
+        );
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.nft_mint("plain".to_string(), accounts(1), sample_token_metadata());
+        assert!(contract.token_attributes("plain".to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_increase_supply() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.tokens_left(), 0);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.increase_supply(2);
+        assert_eq!(get_logs(), vec!["Supply increased from 1 to 3"]);
+        assert_eq!(contract.tokens_left(), 2);
+
+        let token = buy_ticket(&mut context, &mut contract, accounts(2));
+        assert_eq!(token.token_id, "2");
+        assert_eq!(contract.max_supply(), 3);
+        assert_eq!(token.metadata.unwrap().copies, Some(100));
+    }
+
+    #[test]
+    fn test_enumeration_limit_is_clamped() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        assert_eq!(clamp_limit(None), MAX_LIMIT);
+        assert_eq!(clamp_limit(Some(10_000)), MAX_LIMIT);
+        assert_eq!(contract.nft_tokens(None, Some(u64::MAX)).len(), 2);
+        assert_eq!(contract.nft_tokens_for_owner(accounts(1), None, Some(1)).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token id range can span at most 500 ids")]
+    fn test_owners_in_range_above_max_limit() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(0));
+        contract.owners_in_range(1, 501);
+    }
+
+    #[test]
+    fn test_payout_rounding_dust_goes_to_owner() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+
+        // 10001 * 1000 / 10000 = 1000.1, 500.05 and 200.02 all round down.
+        let payout = contract.nft_payout(token.token_id, U128(10001), 4).payout;
+        assert_eq!(payout[&accounts(2)], U128(1000));
+        assert_eq!(payout[&accounts(3)], U128(500));
+        assert_eq!(payout[&accounts(4)], U128(200));
+        assert_eq!(payout[&accounts(1)], U128(8301));
+        assert_eq!(payout.values().map(|amount| amount.0).sum::<u128>(), 10001);
+    }
+
+    #[test]
+    fn test_payout_sums_to_balance_for_awkward_prices() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+
+        for &price in &[1, 7, 9_999, 10_001, 33_333_333, MINTING_PRICE + 9_999, MINTING_PRICE / 3] {
+            for &max_len_payout in &[2, 4] {
+                let payout = contract.nft_payout(token.token_id.clone(), U128(price), max_len_payout).payout;
+                assert_eq!(payout.values().map(|amount| amount.0).sum::<u128>(), price);
+                assert!(payout[&accounts(1)].0 >= price * 83 / 100);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale price must be positive")]
+    fn test_nft_transfer_payout_zero_balance() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.nft_transfer_payout(accounts(5), token.token_id, 0, None, U128(0), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sale price must be positive")]
+    fn test_payout_preview_zero_balance() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_royalties(&mut context);
+        contract.payout_preview(token.token_id, U128(0));
+    }
+
+    #[test]
+    fn test_payout_without_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        let payout = contract.nft_payout(token.token_id, U128(12345), 10).payout;
+        assert_eq!(payout.len(), 1);
+        assert_eq!(payout[&accounts(1)], U128(12345));
+    }
+
+    #[test]
+    fn test_payout_owner_is_royalty_recipient() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+        contract.perpetual_royalties.as_mut().unwrap().insert(accounts(1), 2000);
+
+        // The owner's own royalty is folded into the residual rather than paid twice.
+        let payout = contract.nft_payout(token.token_id, U128(10000), 10).payout;
+        assert_eq!(payout.len(), 4);
+        assert_eq!(payout[&accounts(1)], U128(8300));
+    }
+
+    #[test]
+    fn test_nft_transfer_payout() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        let payout = contract
+            .nft_transfer_payout(accounts(5), token.token_id.clone(), 0, None, U128(MINTING_PRICE), 4)
+            .payout;
+        assert_eq!(payout[&accounts(2)], U128(MINTING_PRICE / 10));
+        assert_eq!(payout[&accounts(3)], U128(MINTING_PRICE / 20));
+        assert_eq!(payout[&accounts(4)], U128(MINTING_PRICE / 50));
+        assert_eq!(payout[&accounts(1)], U128(MINTING_PRICE * 83 / 100));
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(5));
+    }
+
+    #[test]
+    fn test_nft_transfer_payout_with_one_yocto() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_payout_confirmation_required(true);
+
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(5)]);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(1)
+            .build());
+        let payout = contract.nft_transfer_payout(accounts(3), token.token_id.clone(), 1, None, U128(MINTING_PRICE), 4);
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(3));
+        assert_eq!(contract.transfer_history(token.token_id.clone(), None, None).len(), 1);
+
+        contract.confirm_payout(token.token_id, payout.payout);
+        assert!(contract.flagged_payouts(None, None).is_empty());
+    }
+
+    fn sell_through_marketplace(context: &mut VMContextBuilder) -> (Contract, Token, Payout) {
+        let (mut contract, token) = contract_with_royalties(context);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_payout_confirmation_required(true);
+        assert!(contract.payout_confirmation_required());
+
+        approve_accounts(context, &mut contract, &token.token_id, &[accounts(5)]);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(5))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .build());
+        let payout =
+            contract.nft_transfer_payout(accounts(5), token.token_id.clone(), 1, None, U128(MINTING_PRICE), 4);
+        (contract, token, payout)
+    }
+
+    #[test]
+    fn test_confirm_payout() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token, payout) = sell_through_marketplace(&mut context);
+
+        contract.confirm_payout(token.token_id, payout.payout);
+        assert!(contract.flagged_payouts(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_confirm_payout_mismatch() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token, payout) = sell_through_marketplace(&mut context);
+        let mut paid = payout.payout;
+        paid.remove(&accounts(2));
+
+        testing_env!(context.block_timestamp(42).build());
+        contract.confirm_payout(token.token_id.clone(), paid);
+        assert_eq!(
+            contract.flagged_payouts(None, None),
+            vec![FlaggedPayout { token_id: token.token_id, marketplace_id: accounts(5), flagged_at: 42 }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Only the marketplace that settled the sale can confirm its payout")]
+    fn test_confirm_payout_not_marketplace() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token, payout) = sell_through_marketplace(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.confirm_payout(token.token_id, payout.payout);
+    }
+
+    #[test]
+    fn test_payout_default_pays_all_royalties() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        let payout = contract.nft_payout_default(token.token_id.clone(), U128(10000)).payout;
+        assert_eq!(payout.len(), 4);
+        assert_eq!(payout[&accounts(1)], U128(8300));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        let transfer_payout = contract
+            .nft_transfer_payout_default(accounts(5), token.token_id.clone(), 0, None, U128(10000))
+            .payout;
+        assert_eq!(transfer_payout, payout);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(5));
+    }
+
+    #[test]
+    fn test_nft_transfer_with_royalty() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1700 + TRANSFER_STORAGE_COST)
+            .build());
+        contract.nft_transfer_with_royalty(accounts(5), token.token_id.clone(), U128(10000));
+        assert_eq!(transferred_to(&accounts(2)), Some(1000));
+        assert_eq!(transferred_to(&accounts(3)), Some(500));
+        assert_eq!(transferred_to(&accounts(4)), Some(200));
+        assert_eq!(transferred_to(&accounts(5)), None);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Must attach 1700 yoctoNEAR of royalties on top of the transfer deposit")]
+    fn test_nft_transfer_with_royalty_underfunded() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1700).build());
+        contract.nft_transfer_with_royalty(accounts(5), token.token_id, U128(10000));
+    }
+
+    #[test]
+    fn test_with_redeemed_flag() {
+        assert_eq!(with_redeemed_flag("".to_string(), true), r#"{"redeemed":true}"#);
+        assert_eq!(
+            with_redeemed_flag(r#"{"reward":"vip"}"#.to_string(), false),
+            r#"{"redeemed":false,"reward":"vip"}"#
+        );
+        assert_eq!(with_redeemed_flag("plain text".to_string(), true), "plain text");
+    }
+
+    #[test]
+    fn test_transfer_call_rejected_returns_redeemed_token() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id.clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.nft_transfer_call(accounts(2), token.token_id.clone(), None, None, "".to_string());
+        let on_transfer_args = get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id == accounts(2))
+            .flat_map(|receipt| receipt.actions)
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, args, .. } if function_name == "nft_on_transfer" => {
+                    Some(args)
+                }
+                _ => None,
+            })
+            .unwrap();
+        let on_transfer_args: serde_json::Value = serde_json::from_slice(&on_transfer_args).unwrap();
+        assert_eq!(on_transfer_args["msg"], r#"{"redeemed":true}"#);
+
+        // The receiver returns `true` from nft_on_transfer, rejecting the ticket.
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).attached_deposit(0).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"true".to_vec())]
+        );
+        assert!(!contract.nft_resolve_transfer(accounts(1), accounts(2), token.token_id.clone(), None));
+
+        let returned = contract.nft_token(token.token_id).unwrap();
+        assert_eq!(returned.owner_id, accounts(1));
+        let attributes = extra_to_attributes(&returned.metadata.as_ref().unwrap().extra);
+        assert_eq!(get_attribute(&attributes, REDEEMED_TRAIT), Some("true"));
+        assert_eq!(returned.metadata.unwrap().title, token.metadata.unwrap().title);
+    }
+
+    #[test]
+    fn test_set_event_info() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_event_info(sample_event_info());
+        assert_eq!(contract.event_info(), sample_event_info());
+    }
+
+    #[test]
+    fn test_nft_ticket_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_event_info(sample_event_info());
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        assert_eq!(
+            contract.nft_ticket_metadata(),
+            TicketContractMetadata {
+                metadata: contract.nft_metadata(),
+                event_info: sample_event_info(),
+                price: U128(MINTING_PRICE),
+                total_supply: 1,
+                redeemed_count: 0,
+                soulbound: false,
+                transfers_locked_until: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_nft_ticket_metadata_after_burn() {
+        let mut context = get_context(accounts(0));
+        let contract = contract_with_burned_ticket(&mut context);
+        assert_eq!(contract.nft_ticket_metadata().total_supply, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Tickets have already been minted")]
+    fn test_set_event_info_after_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_event_info(sample_event_info());
+    }
+
+    #[test]
+    fn test_buy_with_storage_balance() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(min_balance + 5)
+            .build());
+        let balance = contract.storage_deposit(None, Some(true));
+        assert_eq!(balance.total, U128(min_balance));
+        assert_eq!(balance.available, U128(0));
+        assert_eq!(transferred_to(&accounts(1)), Some(5));
+
+        testing_env!(context.attached_deposit(BUY_STORAGE_COST).build());
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total, U128(min_balance + BUY_STORAGE_COST));
+        assert_eq!(balance.available, U128(BUY_STORAGE_COST));
+
+        // Only the minting price is attached; storage comes out of the balance.
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE)
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+        let available = contract.storage_balance_of(accounts(1)).unwrap().available.0;
+        assert!(available > 0 && available < BUY_STORAGE_COST);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.total, U128(min_balance));
+        assert_eq!(balance.available, U128(0));
+        assert_eq!(transferred_to(&accounts(1)), Some(available));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Can't withdraw more than the available 5 yoctoNEAR")]
+    fn test_storage_withdraw_registration_minimum() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let min_balance = contract.storage_balance_bounds().min.0;
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(min_balance + 5).build());
+        contract.storage_deposit(None, None);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.storage_withdraw(Some(U128(6)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Must attach at least")]
+    fn test_storage_deposit_below_minimum() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    fn test_redeem_within_grace_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.expires_at = Some("1000".to_string());
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_redemption_grace_ns(500);
+        assert_eq!(contract.redemption_grace_ns(), 500);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1500).attached_deposit(1).build());
+        assert!(!contract.is_expired(token.token_id.clone()));
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Ticket expired")]
+    fn test_redeem_past_grace_period() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.expires_at = Some("1000".to_string());
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_redemption_grace_ns(500);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.block_timestamp(1501).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    fn test_redeemed_count_by_tier() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 10);
+        contract.add_tier("GA".to_string(), U128(MINTING_PRICE), 10);
+
+        let mut tokens = vec![];
+        for tier in ["VIP", "VIP", "GA"] {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .predecessor_account_id(accounts(1))
+                .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+                .build());
+            tokens.push(contract.nft_buy_tier(tier.to_string(), None));
+        }
+        let plain = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.tier_of(tokens[0].token_id.clone()), Some("VIP".to_string()));
+        assert_eq!(contract.tier_of(plain.token_id.clone()), None);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.redeem_nft(tokens[0].token_id.clone());
+        contract.redeem_nft(tokens[2].token_id.clone());
+        contract.redeem_nft(plain.token_id);
+        assert_eq!(contract.redeemed_count_by_tier("VIP".to_string()), 1);
+        assert_eq!(contract.redeemed_count_by_tier("GA".to_string()), 1);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.unredeem_nft(tokens[0].token_id.clone());
+        assert_eq!(contract.redeemed_count_by_tier("VIP".to_string()), 0);
+        assert_eq!(contract.redeemed_count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The tier attribute is reserved")]
+    fn test_buy_with_tier_attribute() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(MINTING_PRICE + BUY_STORAGE_COST).build());
+        contract.nft_buy(None, Some(vec![(TIER_TRAIT.to_string(), "VIP".to_string())]), None, None, None, None, None, None);
+    }
+
+    #[test]
+    fn test_transfer_after_cooldown() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1000).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_cooldown_ns(Some(500));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.transferable_at(token.token_id.clone()), 1500);
+
+        testing_env!(context
+            .block_timestamp(1500)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(2));
+    }
+
+    fn redeemed_ticket(context: &mut VMContextBuilder, lock_transfer_after_redeem: bool) -> (Contract, Token) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_lock_transfer_after_redeem(lock_transfer_after_redeem);
+        assert_eq!(contract.lock_transfer_after_redeem(), lock_transfer_after_redeem);
+        let token = buy_ticket(context, &mut contract, accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(token.token_id.clone());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        (contract, token)
+    }
+
+    #[test]
+    fn test_transfer_redeemed_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = redeemed_ticket(&mut context, false);
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Redeemed tickets are non-transferable")]
+    fn test_transfer_redeemed_ticket_locked() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = redeemed_ticket(&mut context, true);
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    fn permit_signature(keypair: &Keypair, token_id: &TokenId, receiver_id: &AccountId, nonce: u64) -> Vec<u8> {
+        let message = format!("{}:{}:{}:{}:{}", token_id, receiver_id, nonce, 100, contract_account_id());
+        keypair.sign(message.as_bytes()).to_bytes().to_vec()
+    }
+
+    fn contract_with_permit_key(context: &mut VMContextBuilder, keypair: &Keypair) -> (Contract, Token) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(context, &mut contract, accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(BUY_STORAGE_COST).build());
+        contract.register_permit_key(keypair.public.to_bytes().to_vec());
+        assert_eq!(contract.permit_key(accounts(1)), Some((keypair.public.to_bytes().to_vec(), 0)));
+        (contract, token)
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_register_permit_key_without_deposit() {
+        let mut context = get_context(accounts(0));
+        let keypair = allowlist_keypair(1);
+        let (mut contract, _) = contract_with_permit_key(&mut context, &keypair);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(0).build());
+        contract.register_permit_key(allowlist_keypair(2).public.to_bytes().to_vec());
+    }
+
+    fn transfer_with_permit(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        keypair: &Keypair,
+        token_id: &TokenId,
+        receiver_id: AccountId,
+        nonce: u64,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .block_timestamp(100)
+            .build());
+        let signature = permit_signature(keypair, token_id, &receiver_id, nonce);
+        contract.nft_transfer_with_permit(token_id.clone(), receiver_id, signature, nonce, 100);
+    }
+
+    #[test]
+    fn test_transfer_with_permit() {
+        let mut context = get_context(accounts(0));
+        let keypair = allowlist_keypair(1);
+        let (mut contract, token) = contract_with_permit_key(&mut context, &keypair);
+
+        transfer_with_permit(&mut context, &mut contract, &keypair, &token.token_id, accounts(2), 7);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(2));
+        assert_eq!(contract.permit_key(accounts(1)).unwrap().1, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Permit nonce already used")]
+    fn test_transfer_with_replayed_permit() {
+        let mut context = get_context(accounts(0));
+        let keypair = allowlist_keypair(1);
+        let (mut contract, token) = contract_with_permit_key(&mut context, &keypair);
+        let second = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        transfer_with_permit(&mut context, &mut contract, &keypair, &token.token_id, accounts(2), 1);
+        transfer_with_permit(&mut context, &mut contract, &keypair, &second.token_id, accounts(2), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Permit expired")]
+    fn test_transfer_with_expired_permit() {
+        let mut context = get_context(accounts(0));
+        let keypair = allowlist_keypair(1);
+        let (mut contract, token) = contract_with_permit_key(&mut context, &keypair);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .block_timestamp(101)
+            .build());
+        let signature = permit_signature(&keypair, &token.token_id, &accounts(2), 1);
+        contract.nft_transfer_with_permit(token.token_id, accounts(2), signature, 1, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Invalid signature")]
+    fn test_transfer_with_permit_for_other_receiver() {
+        let mut context = get_context(accounts(0));
+        let keypair = allowlist_keypair(1);
+        let (mut contract, token) = contract_with_permit_key(&mut context, &keypair);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .block_timestamp(100)
+            .build());
+        let signature = permit_signature(&keypair, &token.token_id, &accounts(2), 1);
+        contract.nft_transfer_with_permit(token.token_id, accounts(4), signature, 1, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token in transfer cooldown")]
+    fn test_transfer_in_cooldown() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1000).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_cooldown_ns(Some(500));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .block_timestamp(1499)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    fn test_owner_transfer_exempt_from_cooldown() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.block_timestamp(1000).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_cooldown_ns(Some(500));
+        let token = buy_ticket(&mut context, &mut contract, accounts(0));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, None);
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(2));
+    }
+
+    #[test]
+    fn test_buy_retry_with_request_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let token = contract.nft_buy(None, None, None, Some("req-1".to_string()), None, None, None, None);
+
+        // The retry refunds the deposit and returns the same ticket.
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let retried = contract.nft_buy(None, None, None, Some("req-1".to_string()), None, None, None, None);
+        assert_eq!(retried.token_id, token.token_id);
+        assert_eq!(transferred_to(&accounts(1)), Some(MINTING_PRICE + BUY_STORAGE_COST));
+        assert_eq!(contract.nft_total_supply(), U128(1));
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        let other = contract.nft_buy(None, None, None, Some("req-2".to_string()), None, None, None, None);
+        assert_ne!(other.token_id, token.token_id);
+    }
+
+    #[test]
+    fn test_buy_request_id_storage_is_charged() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy(None, None, None, None, None, None, None, None);
+        let refund_without_request_id = transferred_to(&accounts(1)).unwrap();
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy(None, None, None, Some("req-1".to_string()), None, None, None, None);
+        assert!(transferred_to(&accounts(1)).unwrap() < refund_without_request_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Request ids can be at most 64 characters")]
+    fn test_buy_with_long_request_id() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let request_id = "r".repeat(MAX_REQUEST_ID_LEN + 1);
+        contract.nft_buy(None, None, None, Some(request_id), None, None, None, None);
+    }
+
+    #[test]
+    fn test_payout_preview_matches_settlement() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_royalties(&mut context);
+
+        let preview = contract.payout_preview(token.token_id.clone(), U128(10001)).payout;
+        assert_eq!(preview.len(), 4);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        let settled = contract.nft_transfer_payout(accounts(5), token.token_id, 0, None, U128(10001), 4).payout;
+        assert_eq!(preview, settled);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Metadata is frozen")]
+    fn test_set_token_template_after_freeze() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.freeze_metadata();
+        assert!(contract.metadata_frozen());
+        contract.set_token_template(Some("New title".to_string()), None, None, None, None);
+    }
+
+    #[test]
+    fn test_set_base_uri() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_base_uri("https://cdn.example.com/tickets".to_string());
+        assert_eq!(contract.nft_metadata().base_uri, Some("https://cdn.example.com/tickets".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Base URI must start with http://, https:// or ipfs://")]
+    fn test_set_base_uri_without_scheme() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_base_uri("cdn.example.com".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Metadata is frozen")]
+    fn test_set_base_uri_after_freeze() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.freeze_metadata();
+        contract.set_base_uri("ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi".to_string());
+    }
+
+    fn contract_with_payment_token(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_payment_token(Some(accounts(4)), U128(100));
+
+        let min_balance = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(min_balance + BUY_STORAGE_COST)
+            .build());
+        contract.storage_deposit(None, None);
+        contract
+    }
+
+    #[test]
+    fn test_buy_with_payment_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_payment_token(&mut context);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(0)
+            .build());
+        match contract.ft_on_transfer(accounts(1), U128(150), "".to_string()) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128(50)),
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+        assert_eq!(contract.nft_tokens_for_owner(accounts(1), None, None).len(), 1);
+        assert_eq!(contract.ft_revenue(), U128(100));
+        assert_eq!(contract.total_revenue(), U128(0));
+    }
+
+    #[test]
+    fn test_underpaid_token_payment_mints_nothing() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_payment_token(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(4)).attached_deposit(0).build());
+        match contract.ft_on_transfer(accounts(1), U128(99), "".to_string()) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128(99)),
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+        assert_eq!(contract.nft_total_supply(), U128(0));
+    }
+
+    #[test]
+    fn test_token_payment_rolls_rarity() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_payment_token(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_random_traits(Some(vec![("rare".to_string(), 1)]));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(0)
+            .build());
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+        let token = contract.nft_token("1".to_string()).unwrap();
+        let attributes = extra_to_attributes(&token.metadata.unwrap().extra);
+        assert_eq!(get_attribute(&attributes, RANDOM_TRAIT), Some("rare"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Too many buys from this account in one block")]
+    fn test_token_payment_respects_buys_per_block() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_payment_token(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_max_buys_per_block(Some(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(0)
+            .build());
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token not accepted as payment")]
+    fn test_payment_from_other_token() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_with_payment_token(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(0).build());
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+    }
+
+    fn approve_accounts(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        token_id: &TokenId,
+        account_ids: &[AccountId],
+    ) {
+        for account_id in account_ids {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .predecessor_account_id(contract.tokens.owner_by_id.get(token_id).unwrap())
+                .attached_deposit(200000000000000000000)
+                .build());
+            contract.nft_approve(token_id.clone(), account_id.clone(), None);
+        }
+    }
+
+    #[test]
+    fn test_revoke_refunds_storage() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(2), accounts(3)]);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.nft_revoke(token.token_id.clone(), accounts(2));
+        assert_eq!(
+            transferred_to(&accounts(1)),
+            Some(Balance::from(bytes_for_approved_account_id(&accounts(2))) * env::storage_byte_cost())
+        );
+        assert!(!contract.nft_is_approved(token.token_id.clone(), accounts(2), None));
+        assert!(contract.nft_is_approved(token.token_id, accounts(3), None));
+    }
+
+    #[test]
+    fn test_revoke_all_refunds_storage() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(2), accounts(3)]);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.nft_revoke_all(token.token_id.clone());
+        let released = bytes_for_approved_account_id(&accounts(2)) + bytes_for_approved_account_id(&accounts(3));
+        assert_eq!(transferred_to(&accounts(1)), Some(Balance::from(released) * env::storage_byte_cost()));
+        assert_eq!(get_logs(), vec![format!(
+            "Revoked 2 approvals on token {}, refunding {} yoctoNEAR",
+            token.token_id,
+            Balance::from(released) * env::storage_byte_cost()
+        )]);
+        assert!(!contract.nft_is_approved(token.token_id, accounts(3), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeds hard supply ceiling")]
+    fn test_increase_supply_above_ceiling() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(contract.hard_max_supply(), 1000);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.increase_supply(900);
+        contract.increase_supply(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeds hard supply ceiling")]
+    fn test_add_tier_above_ceiling() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("GA".to_string(), U128(MINTING_PRICE), 600);
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 401);
+    }
+
+    #[test]
+    fn test_rotate_allowlist_signer() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let old_keypair = allowlist_keypair(1);
+        let new_keypair = allowlist_keypair(2);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(old_keypair.public.to_bytes().to_vec());
+        contract.set_allowlist_signer(new_keypair.public.to_bytes().to_vec());
+        assert_eq!(get_logs(), vec!["Allowlist signer set", "Allowlist signer rotated"]);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        let signature = allowlist_signature(&new_keypair, &accounts(1), 1, 100);
+        let token = contract.nft_buy_signed(accounts(1), signature, 1, 100);
+        assert_eq!(token.owner_id, accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Invalid signature")]
+    fn test_buy_signed_with_rotated_out_key() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let old_keypair = allowlist_keypair(1);
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_allowlist_signer(old_keypair.public.to_bytes().to_vec());
+        contract.set_allowlist_signer(allowlist_keypair(2).public.to_bytes().to_vec());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_buy_signed(accounts(1), allowlist_signature(&old_keypair, &accounts(1), 1, 100), 1, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: The allowlist signer must be a 32-byte ed25519 public key")]
+    fn test_set_allowlist_signer_wrong_length() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.set_allowlist_signer(vec![1; 31]);
+    }
+
+    #[test]
+    fn test_nft_tokens_detailed() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 10);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let seated = contract.nft_buy(None, Some(vec![(SEAT_TRAIT.to_string(), "A12".to_string())]), None, None, None, None, None, None);
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.nft_buy_tier("VIP".to_string(), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(seated.token_id);
+
+        let detailed = contract.nft_tokens_detailed(None, None);
+        assert_eq!(detailed.len(), 2);
+        assert!(detailed[0].redeemed);
+        assert_eq!(detailed[0].seat, Some("A12".to_string()));
+        assert_eq!(detailed[0].tier, None);
+        assert!(!detailed[1].redeemed);
+        assert_eq!(detailed[1].tier, Some("VIP".to_string()));
+        assert_eq!(contract.nft_tokens_detailed(Some(U128(1)), Some(1))[0].token.token_id, "2");
+    }
+
+    #[test]
+    fn test_buy_with_promo_code() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_promo_code("EARLY".to_string(), 2500, Some(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(None, None, None, None, Some("EARLY".to_string()), None, None, None);
+        assert_eq!(contract.total_revenue(), U128(MINTING_PRICE * 3 / 4));
+        assert_eq!(contract.promo_code("EARLY".to_string()).unwrap().uses, 1);
+        let refund = transferred_to(&accounts(1)).unwrap();
+        assert!(refund > MINTING_PRICE / 4 && refund < MINTING_PRICE / 4 + BUY_STORAGE_COST);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Promo code exhausted")]
+    fn test_buy_with_exhausted_promo_code() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_promo_code("EARLY".to_string(), 2500, Some(1));
+
+        for _ in 0..2 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+                .build());
+            contract.nft_buy(None, None, None, None, Some("EARLY".to_string()), None, None, None);
+        }
+    }
+
+    #[test]
+    fn test_minting_progress() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 2;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        assert_eq!(
+            contract.minting_progress(),
+            MintingProgress {
+                minted: 1,
+                max_supply: 2,
+                remaining: 1,
+                price: U128(MINTING_PRICE),
+                paused: false,
+                redemptions_locked: false,
+                sale_status: "on_sale".to_string(),
+            }
+        );
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.minting_progress().sale_status, "sold_out");
+    }
+
+    #[test]
+    fn test_purchasability() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        assert_eq!(contract.purchasability(), (true, None));
+
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.purchasability(), (false, Some("sold_out".to_string())));
+    }
+
+    #[test]
+    fn test_last_transfer_memo() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(contract.last_transfer_memo(token.token_id.clone()), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, Some("Lost phone".to_string()));
+        testing_env!(context.storage_usage(env::storage_usage()).predecessor_account_id(accounts(2)).build());
+        contract.nft_transfer(accounts(3), token.token_id.clone(), None, Some("x".repeat(300)));
+        assert_eq!(contract.last_transfer_memo(token.token_id), Some("x".repeat(MAX_STORED_MEMO_LEN)));
+    }
+
+    #[test]
+    fn test_transfer_history() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert!(contract.transfer_history(token.token_id.clone(), None, None).is_empty());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .block_timestamp(10)
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id.clone(), None, Some("Gift".to_string()));
+        assert!(transferred_to(&accounts(1)).unwrap() < TRANSFER_STORAGE_COST);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(20)
+            .build());
+        contract.nft_transfer(accounts(3), token.token_id.clone(), None, None);
+
+        let history = contract.transfer_history(token.token_id.clone(), None, None);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].from, accounts(1));
+        assert_eq!(history[0].to, accounts(2));
+        assert_eq!(history[0].timestamp, 10);
+        assert_eq!(history[0].memo, Some("Gift".to_string()));
+        assert_eq!(history[1].from, accounts(2));
+        assert_eq!(history[1].to, accounts(3));
+        assert_eq!(history[1].memo, None);
+
+        let page = contract.transfer_history(token.token_id, Some(1), Some(5));
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].to, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover the transfer history storage")]
+    fn test_transfer_history_requires_storage_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    /// A freshly initialized contract called by `accounts(1)`, which is not the owner, with the
+    /// 1 yoctoNEAR the privileged setters require attached.
+    fn contract_called_by_non_owner(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_allowlist_signer_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_allowlist_signer(vec![0; 32]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_referral_bps_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_referral_bps(Some(500));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_royalties_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_royalties(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_max_total_royalty_bps_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_max_total_royalty_bps(1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_add_promo_code_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.add_promo_code("EARLY".to_string(), 2500, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_remove_promo_code_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.remove_promo_code("EARLY".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_airdrop_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.airdrop(vec![accounts(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_mint_specific_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.mint_specific("1".to_string(), accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_transfer_fee_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_transfer_fee(Some(U128(1000)), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_add_tier_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.add_tier("VIP".to_string(), U128(MINTING_PRICE), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_tier_metadata_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_tier_metadata("VIP".to_string(), sample_token_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_max_held_per_account_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_max_held_per_account(Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_increase_supply_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.increase_supply(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_redemption_grace_ns_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_redemption_grace_ns(500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_transfer_cooldown_ns_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_transfer_cooldown_ns(Some(500));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_payment_token_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_payment_token(Some(accounts(4)), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_token_template_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_token_template(Some("Final".to_string()), None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_event_info_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_event_info(sample_event_info());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_freeze_metadata_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.freeze_metadata();
+    }
+
+    #[test]
+    fn test_nft_tokens_by_ids() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let first = buy_ticket(&mut context, &mut contract, accounts(1));
+        let second = buy_ticket(&mut context, &mut contract, accounts(2));
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.nft_burn(first.token_id.clone());
+
+        let tokens = contract.nft_tokens_by_ids(vec![
+            second.token_id.clone(),
+            first.token_id,
+            "unknown".to_string(),
+        ]);
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].as_ref().unwrap().token_id, second.token_id);
+        assert!(tokens[1].is_none());
+        assert!(tokens[2].is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Cannot look up more than 500 tokens at once")]
+    fn test_nft_tokens_by_ids_above_max_limit() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(0));
+        contract.nft_tokens_by_ids((0..=MAX_LIMIT).map(|id| id.to_string()).collect());
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(ERR_SOLD_OUT, "Error: Sold out");
+        assert_eq!(ERR_NOT_OWNER, "Owner only");
+        assert_eq!(RedeemError::AlreadyRedeemed.to_string(), ERR_ALREADY_REDEEMED);
+        assert_eq!(RedeemError::NotOwner.to_string(), ERR_NOT_TOKEN_OWNER);
+        assert_eq!(
+            fill(ERR_ROYALTIES_ABOVE_CAP, &[&6000, &5000]),
+            "Error: Royalties add up to 6000 basis points, above the 5000 cap"
+        );
+        assert_eq!(fill(ERR_WAITLIST_DEPOSIT, &[&1]), "Error: Must attach 1 yoctoNEAR to join the waitlist");
+    }
+
+    #[test]
+    fn test_contract_version() {
+        let context = get_context(accounts(0));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(0));
+        assert_eq!(
+            contract.contract_version(),
+            format!("{} (tiers,ft_payment,allowlist,promo_codes,transfer_history,waitlist,poap,refunds,claim_codes,consignments)", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn test_waitlist() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        for account_id in [accounts(2), accounts(3), accounts(4)] {
+            testing_env!(context
+                .predecessor_account_id(account_id)
+                .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+                .build());
+            contract.join_waitlist();
+        }
+        assert_eq!(contract.waitlist_position(accounts(2)), Some(1));
+        assert_eq!(contract.waitlist_position(accounts(4)), Some(3));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+        contract.leave_waitlist();
+        assert_eq!(transferred_to(&accounts(2)), Some(MINTING_PRICE + BUY_STORAGE_COST));
+        assert_eq!(contract.waitlist_position(accounts(2)), None);
+        assert_eq!(contract.waitlist_position(accounts(3)), Some(1));
+        assert_eq!(contract.waitlist_position(accounts(4)), Some(2));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.increase_supply(1);
+        assert_eq!(contract.nft_supply_for_owner(accounts(3)), U128(1));
+        assert!(transferred_to(&accounts(3)).unwrap() < BUY_STORAGE_COST);
+        assert_eq!(contract.waitlist_position(accounts(3)), None);
+        assert_eq!(contract.waitlist_position(accounts(4)), Some(1));
+        assert_eq!(contract.tokens_left(), 0);
+        assert_eq!(contract.total_revenue(), U128(2 * MINTING_PRICE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_leave_waitlist_without_deposit() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.join_waitlist();
+        testing_env!(context.attached_deposit(0).build());
+        contract.leave_waitlist();
+    }
+
+    #[test]
+    fn test_serve_full_waitlist_gas() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        buy_ticket(&mut context, &mut contract, accounts(1));
 
+        for n in 0..MAX_WAITLIST_LEN {
+            testing_env!(context
+                .predecessor_account_id(format!("fan{}.near", n).parse().unwrap())
+                .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+                .build());
+            contract.join_waitlist();
+        }
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        contract.increase_supply(MAX_WAITLIST_LEN);
+        assert_eq!(contract.tokens_left(), 0);
+        // Unit tests only meter host calls, so leave half the limit for the contract's own code.
+        assert!(env::used_gas() < Gas(150_000_000_000_000));
+    }
+
+    #[test]
+    fn test_refund_serves_waitlist() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_refund_policy(&mut context);
+        contract.max_supply = 1;
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.join_waitlist();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(9_500)
+            .build());
+        contract.refund_ticket(token.token_id.clone());
+        resolve_refund(&mut context, &mut contract, &token.token_id, PromiseResult::Successful(vec![]));
+
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(2));
+        assert_eq!(contract.waitlist_position(accounts(2)), None);
+        assert!(transferred_to(&accounts(2)).unwrap() < BUY_STORAGE_COST);
+        assert_eq!(contract.total_burned(), 0);
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.proceeds(), U128(3 * MINTING_PRICE / 2));
+    }
+
+    #[test]
+    fn test_waitlist_not_served_after_mint_end() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.max_supply = 1;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.join_waitlist();
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_mint_end_ns(Some(1000));
+        testing_env!(context.block_timestamp(1000).build());
+        contract.increase_supply(1);
+        assert_eq!(contract.waitlist_position(accounts(2)), Some(1));
+        assert_eq!(contract.nft_total_supply(), U128(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Tickets are still on sale")]
+    fn test_join_waitlist_while_on_sale() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.join_waitlist();
+    }
+
+    #[test]
+    fn test_admins() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_admin(accounts(1));
+        assert!(contract.is_admin(accounts(1)));
+        assert!(!contract.is_admin(accounts(0)));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.add_admin(accounts(2));
+        contract.add_promo_code("EARLY".to_string(), 2500, None);
+        contract.remove_admin(accounts(1));
+        assert_eq!(contract.list_admins(), vec![accounts(2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_admin_cannot_set_royalties() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_admin(accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_royalties(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_admin_cannot_mint() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_admin(accounts(1));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(BUY_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.nft_mint("backstage".to_string(), accounts(1), sample_token_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_add_admin_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.add_admin(accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_removed_admin_loses_access() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_admin(accounts(1));
+        contract.remove_admin(accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.add_promo_code("EARLY".to_string(), 2500, None);
+    }
+
+    #[test]
+    fn test_withdraw_to_owner() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(2));
+        assert_eq!(contract.proceeds(), U128(2 * MINTING_PRICE));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.withdraw(Some(U128(MINTING_PRICE)));
+        assert_eq!(transferred_to(&accounts(0)), Some(MINTING_PRICE));
+        assert_eq!(contract.proceeds(), U128(MINTING_PRICE));
+    }
+
+    #[test]
+    fn test_withdraw_with_treasury_split() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        let mut treasury_split = HashMap::new();
+        treasury_split.insert(accounts(2), 7000);
+        treasury_split.insert(accounts(3), 3000);
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_treasury_split(Some(treasury_split));
+        contract.withdraw(None);
+        assert_eq!(transferred_to(&accounts(2)), Some(MINTING_PRICE * 7 / 10));
+        assert_eq!(transferred_to(&accounts(3)), Some(MINTING_PRICE * 3 / 10));
+        assert_eq!(transferred_to(&accounts(0)), None);
+        assert_eq!(contract.proceeds(), U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Treasury split must add up to 10000 basis points")]
+    fn test_set_treasury_split_not_adding_up() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        let mut treasury_split = HashMap::new();
+        treasury_split.insert(accounts(2), 7000);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_treasury_split(Some(treasury_split));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Can't withdraw more than the 1000000000000000000000000 yoctoNEAR of proceeds")]
+    fn test_withdraw_above_proceeds() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.withdraw(Some(U128(2 * MINTING_PRICE)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_withdraw_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.withdraw(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_treasury_split_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_treasury_split(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_consignment_commission_bps_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_consignment_commission_bps(1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_max_buys_per_block_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_max_buys_per_block(Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_block_account_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.block_account(accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_lock_transfer_after_redeem_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_lock_transfer_after_redeem(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_minimal_metadata_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_minimal_metadata(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_payout_confirmation_required_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_payout_confirmation_required(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_mint_end_ns_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_mint_end_ns(Some(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_random_traits_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_random_traits(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_primary_split_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_primary_split(None);
+    }
+
+    #[test]
+    fn test_buy_with_validity_window() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.starts_at = Some("1000".to_string());
+        contract.token_metadata.expires_at = Some("5000".to_string());
+        let festival_pass = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        let day_pass = contract.nft_buy(None, None, None, None, None, Some(2000), Some(3000), None);
+        assert_eq!(contract.validity_window(festival_pass.token_id.clone()), (Some(1000), Some(5000)));
+        assert_eq!(contract.validity_window(day_pass.token_id.clone()), (Some(2000), Some(3000)));
+        assert_eq!(day_pass.metadata.unwrap().expires_at, Some("3000".to_string()));
+
+        testing_env!(context.block_timestamp(1500).attached_deposit(1).build());
+        assert!(!contract.nft_is_redeemable(day_pass.token_id.clone()).0);
+        testing_env!(context.block_timestamp(4000).attached_deposit(1).build());
+        assert!(contract.nft_is_redeemable(festival_pass.token_id).0);
+        assert!(!contract.nft_is_redeemable(day_pass.token_id.clone()).0);
+        testing_env!(context.block_timestamp(2500).attached_deposit(1).build());
+        contract.redeem_nft(day_pass.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: valid_from_ns must be before valid_until_ns")]
+    fn test_buy_with_inverted_validity_window() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + BUY_STORAGE_COST)
+            .build());
+        contract.nft_buy(None, None, None, None, None, Some(3000), Some(2000), None);
+    }
+
+    #[test]
+    fn test_supply_invariant_ok() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.add_tier("GA".to_string(), U128(MINTING_PRICE), 600);
+        assert!(contract.supply_invariant_ok());
+
+        contract.hard_max_supply = 500;
+        assert!(!contract.supply_invariant_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Supply invariant violated")]
+    fn test_mint_with_broken_supply_invariant() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.hard_max_supply = 50;
+        buy_ticket(&mut context, &mut contract, accounts(1));
+    }
+
+    fn sample_poap_metadata() -> TokenMetadata {
+        TokenMetadata { title: Some("I was there".to_string()), ..sample_token_metadata() }
+    }
+
+    #[test]
+    fn test_redeem_mints_poap() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_poap_metadata(Some(sample_poap_metadata()));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id.clone());
+        let poap_id = format!("poap-{}", token.token_id);
+        let poap = contract.nft_token(poap_id.clone()).unwrap();
+        assert_eq!(poap.owner_id, accounts(1));
+        assert_eq!(poap.metadata.unwrap().title, Some("I was there".to_string()));
+        assert_eq!(contract.poaps_minted(), 1);
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(2));
+        assert_eq!(contract.tokens_left(), 99);
+
+        // Redeeming again after an unredeem doesn't mint a second one.
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.unredeem_nft(token.token_id.clone());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id);
+        assert_eq!(contract.poaps_minted(), 1);
+    }
+
+    #[test]
+    fn test_redeem_skips_poap_when_balance_too_low() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_poap_metadata(Some(sample_poap_metadata()));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).account_balance(0).build());
+        contract.redeem_nft(token.token_id.clone());
+        assert!(contract.nft_token(format!("poap-{}", token.token_id)).is_none());
+        assert_eq!(contract.poaps_minted(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Attendance tokens can't be transferred")]
+    fn test_poap_not_transferable() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_poap_metadata(Some(sample_poap_metadata()));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id.clone());
+        testing_env!(context.attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.nft_transfer(accounts(2), format!("poap-{}", token.token_id), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner or admin only")]
+    fn test_set_poap_metadata_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_poap_metadata(None);
+    }
+
+    fn on_transfer_gas() -> Gas {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .find_map(|action| match action {
+                VmAction::FunctionCall { function_name, gas, .. } if function_name == "nft_on_transfer" => Some(gas),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_transfer_call_gas() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.nft_transfer_call(accounts(2), token.token_id.clone(), None, None, "".to_string());
+        assert_eq!(on_transfer_gas(), DEFAULT_GAS_FOR_NFT_ON_TRANSFER);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_default_call_gas(40_000_000_000_000);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .build());
+        contract.nft_transfer_call(accounts(3), token.token_id.clone(), None, None, "".to_string());
+        assert_eq!(on_transfer_gas(), Gas(40_000_000_000_000));
+
+        testing_env!(context.storage_usage(env::storage_usage()).predecessor_account_id(accounts(3)).build());
+        contract.nft_transfer_call_with_gas(
+            accounts(4),
+            token.token_id,
+            None,
+            None,
+            "".to_string(),
+            Some(Gas(60_000_000_000_000)),
+        );
+        assert_eq!(on_transfer_gas(), Gas(60_000_000_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Not enough gas left for the receiver call and resolving the transfer")]
+    fn test_transfer_call_gas_above_prepaid() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.nft_transfer_call_with_gas(
+            accounts(2),
+            token.token_id,
+            None,
+            None,
+            "".to_string(),
+            Some(Gas(100_000_000_000_000)),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_default_call_gas_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_default_call_gas(40_000_000_000_000);
+    }
+
+    #[test]
+    fn test_nft_tokens_for_owner_sorted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        for _ in 0..11 {
+            buy_ticket(&mut context, &mut contract, accounts(1));
+        }
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(0))
+            .attached_deposit(BUY_STORAGE_COST)
+            .build());
+        contract.nft_mint("vip".to_string(), accounts(1), sample_token_metadata());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
             .build());
+        contract.nft_burn("3".to_string());
+
+        let token_ids: Vec<TokenId> = contract
+            .nft_tokens_for_owner_sorted(accounts(1), None, None)
+            .into_iter()
+            .map(|token| token.token_id)
+            .collect();
+        assert_eq!(token_ids, vec!["1", "2", "4", "5", "6", "7", "8", "9", "10", "11", "vip"]);
+
+        let page: Vec<TokenId> = contract
+            .nft_tokens_for_owner_sorted(accounts(1), Some(U128(8)), Some(2))
+            .into_iter()
+            .map(|token| token.token_id)
+            .collect();
+        assert_eq!(page, vec!["10", "11"]);
+        assert!(contract.nft_tokens_for_owner_sorted(accounts(2), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_lock_redemptions() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_redemptions_locked(true);
+        assert!(contract.minting_progress().redemptions_locked);
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id.clone()),
+            (false, Some("redemptions_locked".to_string()))
+        );
+        // Sales keep going while redemptions are locked.
+        buy_ticket(&mut context, &mut contract, accounts(2));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_redemptions_locked(false);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Redemptions are locked")]
+    fn test_redeem_while_locked() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_redemptions_locked(true);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Redemptions are locked")]
+    fn test_redeem_many_while_locked() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
 
-        let token_id = "0".to_string();
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_redemptions_locked(true);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.redeem_many(vec![token.token_id], false);
     }
 
     #[test]
-    fn test_transfer() {
+    #[should_panic(expected = "Owner only")]
+    fn test_set_redemptions_locked_not_owner() {
         let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_redemptions_locked(true);
+    }
+
+    fn contract_with_refund_policy(context: &mut VMContextBuilder) -> (Contract, Token) {
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.event_info.event_date_ns = 10_000;
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_refund_policy(Some(RefundPolicy { full_refund_window_ns: 1000, partial_refund_bps: 5000 }));
+        let token = buy_ticket(context, &mut contract, accounts(1));
+        (contract, token)
+    }
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+    #[test]
+    fn test_refund_quote_schedule() {
+        let mut context = get_context(accounts(0));
+        let (contract, token) = contract_with_refund_policy(&mut context);
+
+        for (now, quote) in [
+            (8_999, MINTING_PRICE),
+            (9_000, MINTING_PRICE / 2),
+            (9_999, MINTING_PRICE / 2),
+            (10_000, 0),
+        ] {
+            testing_env!(context.block_timestamp(now).build());
+            assert_eq!(contract.refund_quote(token.token_id.clone()), U128(quote), "at {}", now);
+        }
+    }
+
+    #[test]
+    fn test_refund_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_refund_policy(&mut context);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(1))
             .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
+            .block_timestamp(9_500)
             .build());
-        contract.nft_transfer(accounts(1), token_id.clone(), None, None);
+        assert_eq!(contract.refund_ticket(token.token_id.clone()), U128(MINTING_PRICE / 2));
+        assert_eq!(transferred_to(&accounts(1)), Some(MINTING_PRICE / 2));
+        assert_eq!(contract.proceeds(), U128(MINTING_PRICE / 2));
+
+        resolve_refund(&mut context, &mut contract, &token.token_id, PromiseResult::Successful(vec![]));
+        assert!(transferred_to(&accounts(1)).is_some_and(|storage_refund| storage_refund > 0));
+        assert!(contract.nft_token(token.token_id).is_none());
+        assert_eq!(contract.proceeds(), U128(MINTING_PRICE / 2));
+        assert_eq!(contract.total_revenue(), U128(MINTING_PRICE / 2));
+    }
+
+    #[test]
+    fn test_sales_summary() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, refunded) = contract_with_refund_policy(&mut context);
+        let redeemed = buy_ticket(&mut context, &mut contract, accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(redeemed.token_id);
+
+        testing_env!(context.storage_usage(env::storage_usage()).block_timestamp(9_500).build());
+        contract.refund_ticket(refunded.token_id.clone());
+        resolve_refund(&mut context, &mut contract, &refunded.token_id, PromiseResult::Successful(vec![]));
+
+        assert_eq!(
+            contract.sales_summary(),
+            SalesSummary {
+                total_minted: 2,
+                total_redeemed: 1,
+                total_burned: 1,
+                gross_revenue: U128(2 * MINTING_PRICE),
+                refunds_issued: U128(MINTING_PRICE / 2),
+                current_balance: U128(3 * MINTING_PRICE / 2),
+            }
+        );
+    }
+
+    fn resolve_refund(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        token_id: &TokenId,
+        result: PromiseResult,
+    ) {
+        testing_env!(
+            context.storage_usage(env::storage_usage()).predecessor_account_id(contract_account_id()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+        contract.resolve_refund(token_id.clone(), accounts(1), U128(MINTING_PRICE / 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: A refund of this ticket is in progress")]
+    fn test_refund_ticket_twice() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_refund_policy(&mut context);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(9_500)
             .build());
-        if let Some(token) = contract.nft_token(token_id.clone()) {
-            assert_eq!(token.token_id, token_id);
-            assert_eq!(token.owner_id.to_string(), accounts(1).to_string());
-            assert_eq!(token.metadata.unwrap(), sample_token_metadata());
-            assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
-        } else {
-            panic!("token not correctly created, or not found by nft_token");
-        }
+        contract.refund_ticket(token.token_id.clone());
+        // The first refund's payment hasn't resolved yet, so the ticket still exists.
+        contract.refund_ticket(token.token_id);
     }
 
     #[test]
-    fn test_approve() {
+    fn test_failed_refund_releases_ticket() {
         let mut context = get_context(accounts(0));
-        testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let (mut contract, token) = contract_with_refund_policy(&mut context);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_timestamp(9_500)
             .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        contract.refund_ticket(token.token_id.clone());
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id.clone()),
+            (false, Some("refund_in_progress".to_string()))
+        );
+
+        resolve_refund(&mut context, &mut contract, &token.token_id, PromiseResult::Failed);
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(1));
+        assert_eq!(contract.proceeds(), U128(MINTING_PRICE));
+        assert_eq!(contract.nft_is_redeemable(token.token_id), (true, None));
+    }
+
+    #[test]
+    fn test_supply_after_burns() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, refunded) = contract_with_refund_policy(&mut context);
+        let burned = buy_ticket(&mut context, &mut contract, accounts(1));
+        buy_ticket(&mut context, &mut contract, accounts(2));
 
-        // alice approves bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
             .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+        contract.nft_burn(burned.token_id);
+        contract.refund_ticket(refunded.token_id.clone());
+        resolve_refund(&mut context, &mut contract, &refunded.token_id, PromiseResult::Successful(vec![]));
+
+        assert_eq!(contract.total_burned(), 2);
+        assert_eq!(
+            contract.minted_tokens + contract.poaps_minted() - contract.total_burned(),
+            contract.nft_total_supply().0 as u64
+        );
+        assert_eq!(contract.nft_total_supply(), U128(1));
+        assert_eq!(contract.tokens_left(), 97);
+    }
+
+    fn contract_with_consigned_ticket(context: &mut VMContextBuilder) -> (Contract, Token) {
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_consignment_commission_bps(1000);
+        let token = buy_ticket(context, &mut contract, accounts(1));
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
             .build());
-        assert!(contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        contract.consign(token.token_id.clone(), U128(MINTING_PRICE));
+        (contract, token)
     }
 
     #[test]
-    fn test_revoke() {
+    fn test_consigned_tickets_pages() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        for n in 0..MAX_LIMIT + 10 {
+            if n % 100 == 0 {
+                // A fresh context per batch keeps the inserts within one call's gas.
+                testing_env!(context.build());
+            }
+            contract.consignments.insert(&format!("consigned-{}", n), &(accounts(1), MINTING_PRICE));
+        }
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        testing_env!(context.is_view(true).build());
+        assert_eq!(contract.consigned_tickets(None, None).len() as u64, MAX_LIMIT);
+        testing_env!(context.build());
+        let last_page = contract.consigned_tickets(Some(MAX_LIMIT), None);
+        assert_eq!(last_page.len(), 10);
+        assert_eq!(last_page[0].0, format!("consigned-{}", MAX_LIMIT));
+        assert_eq!(contract.consigned_tickets(Some(2), Some(3)).len(), 3);
+    }
 
-        // alice approves bob
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
-            .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+    #[test]
+    fn test_consign_and_resell() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(0));
+        assert_eq!(
+            contract.consigned_tickets(None, None),
+            vec![(token.token_id.clone(), accounts(1), U128(MINTING_PRICE))]
+        );
+        let proceeds = contract.proceeds().0;
 
-        // alice revokes bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINTING_PRICE + TRANSFER_STORAGE_COST)
             .build());
-        contract.nft_revoke(token_id.clone(), accounts(1));
+        contract.resell_consigned(token.token_id.clone(), accounts(3));
+        assert_eq!(contract.nft_token(token.token_id).unwrap().owner_id, accounts(3));
+        assert!(contract.consigned_tickets(None, None).is_empty());
+        assert_eq!(contract.proceeds(), U128(proceeds + MINTING_PRICE / 10));
+        let payment = transferred_to(&accounts(1)).unwrap();
+        assert!(payment > MINTING_PRICE * 9 / 10 && payment < MINTING_PRICE * 9 / 10 + TRANSFER_STORAGE_COST);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Must attach the 1000000000000000000000000 yoctoNEAR asking price plus 1 yoctoNEAR")]
+    fn test_resell_consigned_below_asking_price() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(MINTING_PRICE).build());
+        contract.resell_consigned(token.token_id, accounts(2));
+    }
+
+    #[test]
+    fn test_cancel_consignment() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(TRANSFER_STORAGE_COST)
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), None));
+        contract.cancel_consignment(token.token_id.clone());
+        assert_eq!(contract.nft_token(token.token_id.clone()).unwrap().owner_id, accounts(1));
+        assert!(contract.consigned_tickets(None, None).is_empty());
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(token.token_id);
     }
 
     #[test]
-    fn test_revoke_all() {
+    #[should_panic(expected = "Error: Only the consignor or the owner can cancel a consignment")]
+    fn test_cancel_consignment_not_consignor() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.cancel_consignment(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is consigned for resale")]
+    fn test_transfer_consigned_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is consigned for resale")]
+    fn test_burn_consigned_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.nft_burn(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is consigned for resale")]
+    fn test_redeem_consigned_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_consigned_ticket(&mut context);
+        assert_eq!(
+            contract.nft_is_redeemable(token.token_id.clone()),
+            (false, Some("consigned".to_string()))
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.redeem_nft(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Token not owned by the caller")]
+    fn test_consign_not_holder() {
         let mut context = get_context(accounts(0));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(0).into());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST)
-            .predecessor_account_id(accounts(0))
-            .build());
-        let token_id = "0".to_string();
-        contract.nft_mint(token_id.clone(), accounts(0), sample_token_metadata());
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(TRANSFER_STORAGE_COST).build());
+        contract.consign(token.token_id, U128(MINTING_PRICE));
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is not refundable")]
+    fn test_refund_partly_redeemed_ticket() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.event_info.event_date_ns = 10_000;
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_refund_policy(Some(RefundPolicy { full_refund_window_ns: 1000, partial_refund_bps: 5000 }));
+        contract.set_max_redemptions(2);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).block_timestamp(5_000).build());
+        contract.redeem_nft(token.token_id.clone());
+        assert!(contract.nft_is_redeemable(token.token_id.clone()).0);
+        assert_eq!(contract.refund_quote(token.token_id.clone()), U128(0));
+        contract.refund_ticket(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is not refundable")]
+    fn test_refund_redeemed_ticket() {
+        let mut context = get_context(accounts(0));
+        let (mut contract, token) = contract_with_refund_policy(&mut context);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).block_timestamp(5_000).build());
+        contract.redeem_nft(token.token_id.clone());
+        contract.refund_ticket(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Error: Ticket is not refundable")]
+    fn test_refund_without_policy() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(1).build());
+        contract.refund_ticket(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_refund_policy_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_refund_policy(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many approvals")]
+    fn test_approve_above_max_approvals() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        let marketplaces: Vec<AccountId> = (0..=DEFAULT_MAX_APPROVALS_PER_TOKEN)
+            .map(|i| format!("mkt{}", i).parse().unwrap())
+            .collect();
+        let (allowed, rejected) = marketplaces.split_at(DEFAULT_MAX_APPROVALS_PER_TOKEN as usize);
+
+        approve_accounts(&mut context, &mut contract, &token.token_id, allowed);
+        assert_eq!(contract.max_approvals_per_token(), DEFAULT_MAX_APPROVALS_PER_TOKEN);
+        // Re-approving an account that's already approved doesn't count against the cap.
+        approve_accounts(&mut context, &mut contract, &token.token_id, &allowed[..1]);
+        approve_accounts(&mut context, &mut contract, &token.token_id, rejected);
+    }
+
+    #[test]
+    fn test_minimal_metadata() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        contract.token_metadata.description = Some("Doors open at 7pm, no re-entry".repeat(2));
+        contract.token_metadata.reference = Some("https://example.com/ticket.json".to_string());
+        contract.token_metadata.reference_hash = Some(Base64VecU8(vec![0; 32]));
+        contract.token_metadata.expires_at = Some("1000".to_string());
+
+        let storage_before = env::storage_usage();
+        let full = buy_ticket(&mut context, &mut contract, accounts(1));
+        let full_storage = env::storage_usage() - storage_before;
+
+        testing_env!(context.predecessor_account_id(accounts(0)).attached_deposit(1).build());
+        contract.set_minimal_metadata(true);
+        assert!(contract.minimal_metadata());
+        let storage_before = env::storage_usage();
+        let minimal = buy_ticket(&mut context, &mut contract, accounts(1));
+        let minimal_storage = env::storage_usage() - storage_before;
+
+        assert!(full.metadata.unwrap().description.is_some());
+        let minimal_metadata = minimal.metadata.unwrap();
+        assert_eq!(minimal_metadata.title, contract.token_metadata.title);
+        assert_eq!(minimal_metadata.media, contract.token_metadata.media);
+        assert_eq!(minimal_metadata.expires_at, Some("1000".to_string()));
+        assert_eq!(minimal_metadata.description, None);
+        assert_eq!(minimal_metadata.reference, None);
+        assert_eq!(minimal_metadata.reference_hash, None);
+        assert_eq!(minimal_metadata.copies, None);
+        assert_eq!(
+            get_attribute(&extra_to_attributes(&minimal_metadata.extra), REDEEMED_TRAIT),
+            Some("false")
+        );
+        assert!(minimal_storage + 150 < full_storage);
+    }
+
+    #[test]
+    fn test_verify_ownership() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        assert!(!contract.verify_ownership(accounts(1)));
+        assert_eq!(contract.tokens_owned_count(accounts(1)), 0);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert!(contract.verify_ownership(accounts(1)));
+        assert_eq!(contract.tokens_owned_count(accounts(1)), 1);
+        assert!(!contract.owns_redeemed(accounts(1)));
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.redeem_nft(token.token_id.clone());
+        assert!(contract.owns_redeemed(accounts(1)));
 
-        // alice approves bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(150000000000000000000)
-            .predecessor_account_id(accounts(0))
+            .attached_deposit(TRANSFER_STORAGE_COST)
+            .predecessor_account_id(accounts(1))
             .build());
-        contract.nft_approve(token_id.clone(), accounts(1), None);
+        contract.nft_transfer(accounts(2), token.token_id, None, None);
+        assert!(!contract.verify_ownership(accounts(1)));
+        assert!(!contract.owns_redeemed(accounts(1)));
+        assert!(contract.owns_redeemed(accounts(2)));
+        assert_eq!(contract.tokens_owned_count(accounts(1)), 0);
+        assert_eq!(contract.tokens_owned_count(accounts(2)), 1);
+    }
+
+    #[test]
+    fn test_approve_many() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let first = buy_ticket(&mut context, &mut contract, accounts(1));
+        let second = buy_ticket(&mut context, &mut contract, accounts(1));
+        approve_accounts(&mut context, &mut contract, &second.token_id, &[accounts(2)]);
 
-        // alice revokes bob
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(1)
-            .predecessor_account_id(accounts(0))
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(400000000000000000000)
             .build());
-        contract.nft_revoke_all(token_id.clone());
+        let approval_ids =
+            contract.nft_approve_many(vec![first.token_id.clone(), second.token_id.clone()], accounts(3));
+        assert_eq!(approval_ids, vec![1, 2]);
+        assert!(contract.nft_is_approved(first.token_id, accounts(3), Some(1)));
+        assert!(contract.nft_is_approved(second.token_id, accounts(3), Some(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must be token owner.")]
+    fn test_approve_many_with_token_not_owned() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        let owned = buy_ticket(&mut context, &mut contract, accounts(1));
+        let not_owned = buy_ticket(&mut context, &mut contract, accounts(2));
+
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .account_balance(env::account_balance())
-            .is_view(true)
-            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(400000000000000000000)
             .build());
-        assert!(!contract.nft_is_approved(token_id.clone(), accounts(1), Some(1)));
+        contract.nft_approve_many(vec![owned.token_id, not_owned.token_id], accounts(3));
+    }
+
+    #[test]
+    fn test_approve_allowed_marketplace() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.allow_marketplace(accounts(2));
+        assert_eq!(contract.allowed_marketplaces(), vec![accounts(2)]);
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(2)]);
+        assert!(contract.nft_is_approved(token.token_id, accounts(2), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Marketplace not allowlisted")]
+    fn test_approve_marketplace_not_allowlisted() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        contract.allow_marketplace(accounts(2));
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+
+        approve_accounts(&mut context, &mut contract, &token.token_id, &[accounts(3)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_allow_marketplace_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.allow_marketplace(accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_add_claim_codes_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.add_claim_codes(vec![hash_claim_code("SCRATCH-1234")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_max_approvals_per_token_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_max_approvals_per_token(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Owner only")]
+    fn test_set_base_uri_not_owner() {
+        let mut context = get_context(accounts(0));
+        let mut contract = contract_called_by_non_owner(&mut context);
+        contract.set_base_uri("https://cdn.example.com/tickets".to_string());
+    }
+
+    #[test]
+    fn test_padded_token_ids() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new(
+            accounts(0),
+            NFTContractMetadata {
+                spec: NFT_METADATA_SPEC.to_string(),
+                name: "Example NEAR non-fungible token".to_string(),
+                symbol: "EXAMPLE".to_string(),
+                icon: None,
+                base_uri: None,
+                reference: None,
+                reference_hash: None,
+            },
+            TokenMetadata { copies: Some(100), ..sample_token_metadata() },
+            U128(MINTING_PRICE),
+            None,
+            sample_event_info(),
+            1000,
+            true,
+        );
+        let token = buy_ticket(&mut context, &mut contract, accounts(1));
+        assert_eq!(token.token_id, "0001");
+        assert_eq!(contract.owners_in_range(1, 2), vec![("0001".to_string(), accounts(1))]);
     }
-}
\ No newline at end of file
+}